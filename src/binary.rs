@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use crate::dag::{Dag, TaskNode};
+use crate::yaml::TaskSpec;
+
+/// First bytes of every binary export, followed by the format version.
+const MAGIC: &[u8; 4] = b"SDAG";
+const VERSION: u8 = 1;
+
+/// Appends integers little-endian and strings with a `u32` length prefix.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(u32::try_from(len).expect("binary exports hold at most u32::MAX items"));
+    }
+
+    fn str(&mut self, text: &str) {
+        self.len(text.len());
+        self.0.extend_from_slice(text.as_bytes());
+    }
+}
+
+/// Reads what `Writer` wrote, failing on truncated or malformed input.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("invalid binary DAG: unexpected end of input".into());
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("take returns N bytes"))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.array().map(i32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        self.u32().map(|len| len as usize)
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "invalid binary DAG: string is not UTF-8".to_string())
+    }
+}
+
+impl Dag {
+    /// Exports the DAG in a compact binary format read by `from_binary`
+    ///
+    /// Stores each node's id, name, task type, cost, retries, priority and task
+    /// parameters, followed by the edges as pairs of node indexes. Like `to_yaml`, only
+    /// shell and python tasks can be loaded back. Teardown tasks are not exported.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let nodes = self.resolve_execution_order().unwrap_or_else(|_| {
+            let mut nodes = self.get_all_tasks();
+            nodes.sort_by(|a, b| a.name.cmp(&b.name));
+            nodes
+        });
+        let index: HashMap<&TaskNode, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node, index))
+            .collect();
+
+        let mut out = Writer(MAGIC.to_vec());
+        out.0.push(VERSION);
+        out.str(self.name());
+        out.len(nodes.len());
+        for node in &nodes {
+            out.str(node.id());
+            out.str(&node.name);
+            out.str(node.task_type());
+            out.u64(node.cost() as u64);
+            out.u32(node.retries());
+            out.i32(node.priority());
+            let params = node.task_params();
+            out.len(params.len());
+            for (key, value) in &params {
+                out.str(key);
+                out.str(value);
+            }
+        }
+        let edges: Vec<(usize, usize)> = self
+            .edges()
+            .map(|(from, to)| (index[from], index[to]))
+            .collect();
+        out.len(edges.len());
+        for (from, to) in edges {
+            out.len(from);
+            out.len(to);
+        }
+        out.0
+    }
+
+    /// Loads a DAG written by `to_binary`, keeping its node ids.
+    pub fn from_binary(bytes: &[u8]) -> Result<Dag, String> {
+        let mut input = Reader(bytes);
+        if input.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err("invalid binary DAG: missing header".into());
+        }
+        let version = input.array::<1>()?[0];
+        if version != VERSION {
+            return Err(format!(
+                "unsupported binary DAG version {} (expected {})",
+                version, VERSION
+            ));
+        }
+
+        let mut dag = Dag::new(input.str()?);
+        let count = input.len()?;
+        let mut nodes: Vec<TaskNode> = Vec::new();
+        for _ in 0..count {
+            let id = input.str()?;
+            let mut spec = TaskSpec {
+                name: input.str()?,
+                kind: input.str()?,
+                depends_on: Vec::new(),
+                cost: input.u64()? as usize,
+                retries: input.u32()?,
+                priority: input.i32()?,
+                params: Default::default(),
+            };
+            for _ in 0..input.len()? {
+                let key = input.str()?;
+                spec.params.insert(key, input.str()?);
+            }
+            let node = spec.to_node()?.with_id(id);
+            dag.add_task(node.clone());
+            nodes.push(node);
+        }
+
+        for _ in 0..input.len()? {
+            let (from, to) = (input.len()?, input.len()?);
+            let (Some(from), Some(to)) = (nodes.get(from), nodes.get(to)) else {
+                return Err("invalid binary DAG: edge refers to an unknown node".into());
+            };
+            dag.add_task_relation(from.clone(), to.clone());
+        }
+        if !input.0.is_empty() {
+            return Err("invalid binary DAG: trailing bytes".into());
+        }
+        Ok(dag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::task::{PythonTask, ShellTask};
+
+    type Structure = (
+        BTreeSet<(String, String, String)>,
+        BTreeSet<(String, String)>,
+    );
+
+    /// Nodes as (id, name, task type), and edges by id.
+    fn structure(dag: &Dag) -> Structure {
+        let nodes = dag
+            .get_all_tasks()
+            .iter()
+            .map(|node| {
+                let kind = node.task_type().to_string();
+                (node.id().to_string(), node.name.clone(), kind)
+            })
+            .collect();
+        let edges = dag
+            .edges()
+            .map(|(from, to)| (from.id().to_string(), to.id().to_string()))
+            .collect();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn binary_round_trip_of_a_large_dag() {
+        let mut dag = Dag::new("large".into());
+        let nodes: Vec<TaskNode> = (0..1000)
+            .map(|i| {
+                let node = if i % 2 == 0 {
+                    TaskNode::new(
+                        format!("shell-{}", i),
+                        ShellTask::new(format!("echo {}", i)),
+                    )
+                } else {
+                    let task = PythonTask::new(format!("print({})", i));
+                    TaskNode::new(format!("python-{}", i), task).with_priority(i % 7)
+                };
+                dag.add_task_ref(node.with_retries(i as u32 % 3))
+            })
+            .collect();
+        for i in 1..nodes.len() {
+            dag.add_task_relation(nodes[i / 2].clone(), nodes[i].clone());
+            if i % 10 == 0 {
+                dag.add_task_relation(nodes[i - 1].clone(), nodes[i].clone());
+            }
+        }
+
+        let bytes = dag.to_binary();
+        let loaded = Dag::from_binary(&bytes).unwrap();
+        assert_eq!(loaded.name(), "large");
+        assert_eq!(structure(&loaded), structure(&dag));
+        for node in loaded.get_all_tasks() {
+            let original = nodes
+                .iter()
+                .find(|original| original.id() == node.id())
+                .unwrap();
+            assert_eq!(
+                (node.retries(), node.priority(), node.task_params()),
+                (
+                    original.retries(),
+                    original.priority(),
+                    original.task_params()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("a".to_string(), ShellTask::new("true")));
+        let bytes = dag.to_binary();
+
+        assert!(Dag::from_binary(b"JSON").unwrap_err().contains("header"));
+        let truncated = Dag::from_binary(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(truncated.contains("unexpected end"), "{}", truncated);
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION + 1;
+        assert!(
+            Dag::from_binary(&newer)
+                .unwrap_err()
+                .starts_with("unsupported")
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Source of time for timeout and retry logic
+///
+/// Abstracting over time lets tests drive waits deterministically with `MockClock`
+/// instead of sleeping for real.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Clock backed by `Instant::now` and `thread::sleep`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Simulated clock whose time only moves when slept on or advanced
+///
+/// Sleeping returns immediately after advancing the simulated time, and every sleep is
+/// recorded so tests can assert on the requested waits.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    elapsed: Duration,
+    sleeps: Vec<Duration>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock {
+            start: Instant::now(),
+            state: Mutex::new(MockState::default()),
+        }
+    }
+
+    /// Moves simulated time forward without recording a sleep.
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().elapsed += duration;
+    }
+
+    /// Simulated time since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+
+    /// Every duration passed to `sleep`, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().sleeps.clone()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += duration;
+        state.sleeps.push(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_moves_only_when_told() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(5));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.now() - start, Duration::from_secs(6));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(5)]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Shared key/value store for passing data between tasks
+///
+/// Cloning a `Context` yields another handle to the same store, so a clone can be
+/// moved into each task that needs to read or write it. A `child` context reads
+/// through to its parent but keeps its own writes to itself.
+#[derive(Clone, Default)]
+pub struct Context {
+    values: Arc<Mutex<HashMap<String, String>>>,
+    parent: Option<Arc<Context>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new scope, e.g. for a nested pipeline, that sees this context's values
+    ///
+    /// Reads fall back to this context, and to its own parents, for keys the child has
+    /// not set. Writes and removals only touch the child, so they never reach this
+    /// context unless `promote`d; removing a key from the child lets the inherited
+    /// value show through again.
+    pub fn child(&self) -> Context {
+        Context {
+            values: Arc::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    /// Copies the values this context set itself under `keys` into its parent
+    ///
+    /// Keys the context has not set itself are skipped, and a context without a parent
+    /// has nowhere to promote to, so nothing happens.
+    pub fn promote<I, K>(&self, keys: I)
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let Some(parent) = &self.parent else {
+            return;
+        };
+        for key in keys {
+            let value = self.lock().get(key.as_ref()).cloned();
+            if let Some(value) = value {
+                parent.set(key.as_ref(), value);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = self.lock().get(key).cloned();
+        value.or_else(|| self.parent.as_ref()?.get(key))
+    }
+
+    pub fn set<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        self.lock().insert(key.into(), value.into());
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.lock().contains_key(key)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.contains(key))
+    }
+
+    pub fn remove(&self, key: &str) -> Option<String> {
+        self.lock().remove(key)
+    }
+
+    /// Replaces every `${key}` in `template` with the value stored under `key`.
+    ///
+    /// Fails on a key that is not set or a `${` without a closing `}`.
+    pub fn render(&self, template: &str) -> Result<String, String> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("${") {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unterminated placeholder in '{}'", template))?;
+            let key = &after[..end];
+            let value = self
+                .get(key)
+                .ok_or_else(|| format!("context has no value for '${{{}}}'", key))?;
+            rendered.push_str(&value);
+            rest = &after[end + 1..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        // A panicking task must not make the context unusable for everyone else
+        self.values
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Debug for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.parent {
+            None => write!(f, "Context({:?})", *self.lock()),
+            Some(parent) => write!(f, "Context({:?}, parent: {:?})", *self.lock(), parent),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let context = Context::new();
+        context.set("dir", "/data");
+        context.set("name", "in.csv");
+        assert_eq!(
+            context.render("cat ${dir}/${name} $HOME").unwrap(),
+            "cat /data/in.csv $HOME"
+        );
+
+        assert_eq!(
+            context.render("ls ${missing}").unwrap_err(),
+            "context has no value for '${missing}'"
+        );
+        assert!(context.render("ls ${dir").is_err());
+    }
+
+    #[test]
+    fn child_writes_stay_isolated_unless_promoted() {
+        let parent = Context::new();
+        parent.set("env", "prod");
+        parent.set("rows", "10");
+
+        let child = parent.child();
+        assert_eq!(child.get("env").as_deref(), Some("prod"));
+        child.set("rows", "99");
+        child.set("scratch", "tmp");
+        child.set("result", "ok");
+        assert_eq!(child.render("${env}:${rows}").unwrap(), "prod:99");
+        assert_eq!(parent.get("rows").as_deref(), Some("10"));
+        assert!(!parent.contains("scratch"));
+
+        // Values the parent sets later are visible while the child has none of its own
+        parent.set("region", "eu");
+        assert_eq!(child.get("region").as_deref(), Some("eu"));
+        assert_eq!(child.remove("rows").as_deref(), Some("99"));
+        assert_eq!(child.get("rows").as_deref(), Some("10"));
+
+        child.promote(["result", "region", "missing"]);
+        assert_eq!(parent.get("result").as_deref(), Some("ok"));
+        assert_eq!(parent.get("scratch"), None);
+
+        // Grandchildren promote one level at a time
+        let grandchild = child.child();
+        grandchild.set("deep", "1");
+        grandchild.promote(["deep"]);
+        assert!(child.contains("deep"));
+        assert!(!parent.contains("deep"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// Pause switch consulted before each task is dispatched
+///
+/// Pausing only holds back tasks that have not started yet; a task that is already
+/// running carries on to completion.
+#[derive(Debug, Default)]
+pub struct PauseGate {
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl PauseGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        *self.lock() = true;
+    }
+
+    pub fn resume(&self) {
+        *self.lock() = false;
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.lock()
+    }
+
+    /// Blocks while the gate is paused.
+    pub fn wait_until_open(&self) {
+        let mut paused = self.lock();
+        while *paused {
+            paused = self
+                .resumed
+                .wait(paused)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.paused
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Shared flag that cancels a run and kills the subprocesses it started
+///
+/// Clones share the flag. Give a clone to `Scheduler::with_cancel_token` to stop a
+/// run and kill its running processes, or to a subprocess task's `with_cancel_token`
+/// to stop just that task. Once cancelled, a token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Limit on how many subprocess tasks run at once, shared between the tasks it gates
+///
+/// A task takes a slot before spawning its process and gives it back when the process
+/// exits. See `ShellTask::with_slots` and `PythonTask::with_slots`.
+#[derive(Debug)]
+pub struct ProcessSlots {
+    max_concurrency: usize,
+    in_use: Mutex<usize>,
+    released: Condvar,
+    /// How long a task waits for a free slot before failing; forever when unset.
+    permit_acquire_timeout: Option<Duration>,
+}
+
+/// A held slot, given back when dropped.
+#[derive(Debug)]
+pub struct SlotPermit<'a> {
+    slots: &'a ProcessSlots,
+}
+
+impl ProcessSlots {
+    pub fn new(max_concurrency: usize) -> Self {
+        ProcessSlots {
+            max_concurrency,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+            permit_acquire_timeout: None,
+        }
+    }
+
+    /// Fails a task that has waited `timeout` for a slot instead of letting it wait forever.
+    pub fn with_permit_acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.permit_acquire_timeout = Some(timeout);
+        self
+    }
+
+    pub fn permit_acquire_timeout(&self) -> Option<Duration> {
+        self.permit_acquire_timeout
+    }
+
+    /// Number of slots currently held.
+    pub fn in_use(&self) -> usize {
+        *self.lock()
+    }
+
+    /// Blocks until a slot is free and takes it
+    ///
+    /// Fails with `"could not acquire execution slot"` once the acquire timeout passes.
+    pub fn acquire(&self) -> Result<SlotPermit<'_>, String> {
+        let deadline = self
+            .permit_acquire_timeout
+            .map(|timeout| Instant::now() + timeout);
+        let mut in_use = self.lock();
+        while *in_use >= self.max_concurrency {
+            in_use = match deadline {
+                None => self
+                    .released
+                    .wait(in_use)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err("could not acquire execution slot".into());
+                    }
+                    self.released
+                        .wait_timeout(in_use, left)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+            };
+        }
+        *in_use += 1;
+        Ok(SlotPermit { slots: self })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.in_use
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for SlotPermit<'_> {
+    fn drop(&mut self) {
+        *self.slots.lock() -= 1;
+        self.slots.released.notify_one();
+    }
+}
+
+/// Token bucket capping how many retries happen per time window, shared between tasks
+///
+/// Holds up to `max_retries` tokens and refills one every `window / max_retries`, so
+/// bursts of up to `max_retries` retries go through at once and the sustained rate
+/// never exceeds `max_retries` per `window`. A retry that finds the bucket empty
+/// sleeps on the limiter's clock until a token frees up.
+pub struct RetryLimiter {
+    /// Time it takes to refill one token.
+    interval: Duration,
+    /// How far ahead of now the bucket may be drawn down, i.e. the burst size.
+    burst: Duration,
+    clock: Arc<dyn Clock>,
+    /// When the bucket will next be full; `None` until the first retry.
+    full_at: Mutex<Option<Instant>>,
+}
+
+impl RetryLimiter {
+    /// Allows `max_retries` retries per `window`; `max_retries` must be at least 1.
+    pub fn new(max_retries: u32, window: Duration) -> Self {
+        assert!(max_retries > 0, "max_retries must be at least 1");
+        let interval = window / max_retries;
+        RetryLimiter {
+            interval,
+            burst: interval * (max_retries - 1),
+            clock: Arc::new(SystemClock),
+            full_at: Mutex::new(None),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Takes a token, sleeping until one is available.
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut full_at = self
+                    .full_at
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let now = self.clock.now();
+                let due = full_at.map_or(now, |full_at| full_at.max(now));
+                let ahead = due - now;
+                if ahead <= self.burst {
+                    *full_at = Some(due + self.interval);
+                    return;
+                }
+                ahead - self.burst
+            };
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+impl std::fmt::Debug for RetryLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryLimiter")
+            .field("interval", &self.interval)
+            .field("burst", &self.burst)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn wait_blocks_until_resumed() {
+        let gate = PauseGate::new();
+        gate.wait_until_open();
+        gate.pause();
+        assert!(gate.is_paused());
+
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                gate.wait_until_open();
+                tx.send(()).unwrap();
+            });
+            assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+            gate.resume();
+            assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        });
+    }
+
+    #[test]
+    fn slots_limit_holders_and_time_out() {
+        let slots = ProcessSlots::new(1).with_permit_acquire_timeout(Duration::from_millis(20));
+        let held = slots.acquire().unwrap();
+        assert_eq!(slots.in_use(), 1);
+        assert_eq!(
+            slots.acquire().unwrap_err(),
+            "could not acquire execution slot"
+        );
+
+        drop(held);
+        assert_eq!(slots.in_use(), 0);
+        assert!(slots.acquire().is_ok());
+    }
+
+    #[test]
+    fn retry_limiter_allows_a_burst_then_the_rate() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let limiter = RetryLimiter::new(2, Duration::from_millis(100)).with_clock(clock.clone());
+        let granted: Vec<Duration> = (0..5)
+            .map(|_| {
+                limiter.acquire();
+                clock.elapsed()
+            })
+            .collect();
+        assert_eq!(
+            granted,
+            [0, 0, 50, 100, 150].map(Duration::from_millis).to_vec()
+        );
+
+        // An idle limiter refills, but never beyond its burst size
+        clock.advance(Duration::from_secs(10));
+        let idle = clock.elapsed();
+        for _ in 0..3 {
+            limiter.acquire();
+        }
+        assert_eq!(clock.elapsed() - idle, Duration::from_millis(50));
+    }
+}
@@ -1,29 +1,466 @@
-use std::collections::{HashMap, VecDeque};
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
+use std::fs;
 use std::hash::{Hash, Hasher};
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::time::Duration;
 
-use crate::task::Task;
+use crate::context::Context;
+pub use crate::dsl::from_dsl;
+use crate::observer::NoopObserver;
+use crate::task::{RunEnv, Task, TaskOutcome, TaskRun, TimeoutTask};
 
 // pub type Task = fn() -> Result<(), String>;
 
+/// Extracts the message from a panic payload, which is usually a `&str` or `String`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+thread_local! {
+    /// How many `catch_panic` calls are running on this thread.
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// Where the last panic caught on this thread happened, and its backtrace.
+    static CAUGHT: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Runs `f`, turning a panic into an error with the panic message and location
+///
+/// The error also carries a backtrace when `RUST_BACKTRACE` enables them. The panic
+/// hook that records these is installed once, on first use, and wraps the hook that was
+/// set before, which still runs for every panic. It is never removed, so a hook the
+/// application sets later replaces it and only loses the location in task errors. It
+/// records nothing for panics outside `catch_panic`.
+pub(crate) fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() > 0 {
+                let location = info
+                    .location()
+                    .map_or_else(|| "unknown location".to_string(), ToString::to_string);
+                CAUGHT.set(Some((location, Backtrace::capture())));
+            }
+            previous(info);
+        }));
+    });
+
+    CATCHING.set(CATCHING.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(CATCHING.get() - 1);
+    result.unwrap_or_else(|payload| {
+        let mut err = format!("task panicked: {}", panic_message(&*payload));
+        if let Some((location, backtrace)) = CAUGHT.take() {
+            err.push_str(&format!(" (at {})", location));
+            if backtrace.status() == BacktraceStatus::Captured {
+                err.push_str(&format!("\nbacktrace:\n{}", backtrace));
+            }
+        }
+        Err(err)
+    })
+}
+
+/// Most task names `Dag::describe_cycles` lists for a single cycle.
+pub const MAX_CYCLE_NAMES: usize = 5;
+
+/// Deterministic sequence of version 4 style uuids, seeded by a number
+///
+/// Two generators with the same seed produce the same ids in the same order. The
+/// sequence is not random in any cryptographic sense.
+#[derive(Debug, Clone)]
+pub struct SeededIds {
+    state: u64,
+}
+
+impl SeededIds {
+    pub fn new(seed: u64) -> Self {
+        SeededIds { state: seed }
+    }
+
+    pub fn next_id(&mut self) -> String {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
+
+    /// SplitMix64 step.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// How a node with several upstream nodes decides it is ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinPolicy {
+    /// Wait for every upstream node to succeed.
+    #[default]
+    All,
+    /// Start as soon as any one upstream node succeeds; the rest are not waited on.
+    ///
+    /// Only the parallel executors can start a node early; sequential execution
+    /// follows the topological order regardless.
+    Any,
+}
+
 pub struct TaskNode {
     id: String,
     pub name: String,
     task: Arc<dyn Task>,
+    cost: usize,
+    retries: u32,
+    priority: i32,
+    join_policy: JoinPolicy,
+    estimated_duration: Option<Duration>,
+    timeout: Option<Duration>,
+    delay: Duration,
+    file_inputs: Vec<PathBuf>,
+    file_outputs: Vec<PathBuf>,
+    fallback: Option<Arc<dyn Task>>,
+    group: Option<String>,
+    resource: Option<String>,
+    sequence: Option<u64>,
 }
 
 impl TaskNode {
     pub fn new<T: Task + 'static>(name: String, task: T) -> Self {
+        Self::from_parts(uuid::Uuid::new_v4().to_string(), name, Arc::new(task))
+    }
+
+    /// Creates a node whose id is derived from its name and task type.
+    ///
+    /// Unlike `new`, the id is the same in every process, so persisted graphs stay
+    /// stable and diffable. The tradeoff is that two nodes with the same name and task
+    /// type collide: they compare equal and the DAG treats them as a single node, so
+    /// names must be unique among hashed nodes.
+    pub fn new_hashed<T: Task + 'static>(name: String, task: T) -> Self {
+        let key = format!("{}:{}", task.type_name(), name);
+        let id = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, key.as_bytes()).to_string();
+        Self::from_parts(id, name, Arc::new(task))
+    }
+
+    /// Creates a node whose id is the next one from `ids`
+    ///
+    /// Meant for tests that need reproducible ids without giving up the random-looking
+    /// format of `new`.
+    pub fn new_seeded<T: Task + 'static>(name: String, task: T, ids: &mut SeededIds) -> Self {
+        Self::from_parts(ids.next_id(), name, Arc::new(task))
+    }
+
+    fn from_parts(id: String, name: String, task: Arc<dyn Task>) -> Self {
         TaskNode {
-            id: uuid::Uuid::new_v4().to_string(),
+            id,
             name,
-            task: Arc::new(task),
+            task,
+            cost: 1,
+            retries: 0,
+            priority: 0,
+            join_policy: JoinPolicy::All,
+            estimated_duration: None,
+            timeout: None,
+            delay: Duration::ZERO,
+            file_inputs: Vec::new(),
+            file_outputs: Vec::new(),
+            fallback: None,
+            group: None,
+            resource: None,
+            sequence: None,
+        }
+    }
+
+    /// Replaces the generated id, e.g. with one read back from an export.
+    pub(crate) fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Adds an edge from this node to `next` in `dag` and returns `next` for further chaining.
+    ///
+    /// `a.then(&mut dag, b).then(&mut dag, c)` builds the chain `a -> b -> c`.
+    pub fn then(&self, dag: &mut Dag, next: TaskNode) -> TaskNode {
+        dag.add_task_relation(self.clone(), next.clone());
+        next
+    }
+
+    /// Sets the relative cost of running this task, used by budgeted executors.
+    ///
+    /// Defaults to 1.
+    pub fn with_cost(mut self, cost: usize) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    pub fn cost(&self) -> usize {
+        self.cost
+    }
+
+    /// Sets how many times a failing task is retried before the node counts as failed.
+    ///
+    /// Defaults to 0.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Sets the scheduling priority; higher values run earlier when several nodes are ready.
+    ///
+    /// Defaults to 0.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Sets how many upstream nodes must finish before this one may start.
+    pub fn with_join_policy(mut self, join_policy: JoinPolicy) -> Self {
+        self.join_policy = join_policy;
+        self
+    }
+
+    pub fn join_policy(&self) -> JoinPolicy {
+        self.join_policy
+    }
+
+    /// Sets how long the task is expected to run, used by `SortStrategy::CriticalPathFirst`.
+    ///
+    /// Nodes without an estimate count as taking no time.
+    pub fn with_estimated_duration(mut self, estimate: Duration) -> Self {
+        self.estimated_duration = Some(estimate);
+        self
+    }
+
+    pub fn estimated_duration(&self) -> Option<Duration> {
+        self.estimated_duration
+    }
+
+    /// Fails each attempt of the task that runs longer than `timeout`
+    ///
+    /// Takes precedence over a scheduler's default task timeout. Like `TimeoutTask`, a
+    /// timed-out subprocess is killed, while other tasks are abandoned rather than stopped.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Waits `delay` once the node is ready, before its task first runs
+    ///
+    /// Meant for steps that must let an external system settle. The wait happens
+    /// inside the node's run, so under a parallel executor it holds its slot.
+    ///
+    /// Defaults to no delay.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Declares the files the task reads; see `is_up_to_date`.
+    pub fn with_file_inputs<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.file_inputs = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn file_inputs(&self) -> &[PathBuf] {
+        &self.file_inputs
+    }
+
+    /// Declares the files the task produces; see `is_up_to_date`.
+    pub fn with_file_outputs<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.file_outputs = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn file_outputs(&self) -> &[PathBuf] {
+        &self.file_outputs
+    }
+
+    /// Whether the declared outputs are at least as new as every declared input
+    ///
+    /// Like Make, a node is up to date when it has outputs, all of them exist, and none
+    /// is older than any input. A missing input never counts as up to date. Executors
+    /// treat an up-to-date node as an instant success without running its task.
+    pub fn is_up_to_date(&self) -> bool {
+        let modified = |path: &PathBuf| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if self.file_outputs.is_empty() {
+            return false;
         }
+        let Some(oldest_output) = self
+            .file_outputs
+            .iter()
+            .map(modified)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|times| times.into_iter().min())
+        else {
+            return false;
+        };
+        self.file_inputs
+            .iter()
+            .all(|input| modified(input).is_some_and(|time| time <= oldest_output))
+    }
+
+    /// Runs `alt` once when the task has failed every attempt
+    ///
+    /// The node succeeds if the fallback does, and only fails when both fail. The
+    /// fallback gets the node's timeout but no retries.
+    pub fn with_fallback(mut self, alt: Arc<dyn Task>) -> Self {
+        self.fallback = Some(alt);
+        self
+    }
+
+    /// This node running its fallback task instead, if it has one.
+    pub(crate) fn fallback_node(&self) -> Option<TaskNode> {
+        let fallback = self.fallback.clone()?;
+        Some(TaskNode::from_parts(
+            self.id.clone(),
+            self.name.clone(),
+            fallback,
+        ))
+    }
+
+    /// Puts the node in a dispatch group, e.g. one per tenant sharing a scheduler;
+    /// see `SortStrategy::GroupRoundRobin`.
+    pub fn with_group<S: Into<String>>(mut self, group: S) -> Self {
+        self.group = Some(group.into());
+        self
     }
 
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Names an external resource the task holds exclusively, e.g. a database lock
+    ///
+    /// Parallel runs never start a node while another node with the same resource is
+    /// running, even when the two are independent in the DAG.
+    pub fn with_resource<S: Into<String>>(mut self, resource: S) -> Self {
+        self.resource = Some(resource.into());
+        self
+    }
+
+    pub fn resource(&self) -> Option<&str> {
+        self.resource.as_deref()
+    }
+
+    /// How many nodes the DAG already had when this node was added to it
+    ///
+    /// `None` on handles taken before the node was added; see `SortStrategy::InsertionOrder`.
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// Runs the task once, turning a panic into an error.
     pub fn execute(&self) -> Result<(), String> {
-        (self.task).execute()
+        self.run().map(drop)
+    }
+
+    /// Like `run`, failing once `timeout` passes when one is given.
+    pub fn run_with_timeout(&self, timeout: Option<Duration>) -> Result<TaskOutcome, String> {
+        self.run_in(timeout, &RunEnv::default())
+    }
+
+    /// Like `run_with_timeout`, with the log file and cancel token of a run; see
+    /// `Task::run_in`.
+    pub fn run_in(
+        &self,
+        timeout: Option<Duration>,
+        env: &RunEnv<'_>,
+    ) -> Result<TaskOutcome, String> {
+        self.run_with_status(timeout, env).result
+    }
+
+    /// Like `run_in`, with the exit status of the subprocess this run started; see
+    /// `Task::run_with_status`.
+    pub fn run_with_status(&self, timeout: Option<Duration>, env: &RunEnv<'_>) -> TaskRun {
+        match timeout {
+            Some(timeout) => {
+                TimeoutTask::shared(self.task.clone(), timeout).run_with_status(&self.id, env)
+            }
+            None => catch_panic(|| Ok(self.task.run_with_status(&self.id, env)))
+                .unwrap_or_else(|err| Err(err).into()),
+        }
+    }
+
+    /// Like `execute`, keeping any warnings the task reports on success.
+    pub fn run(&self) -> Result<TaskOutcome, String> {
+        self.run_with_timeout(None)
+    }
+
+    /// Exit status of the subprocess from the task's most recent run; see `Task::exit_code`.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.task.exit_code()
+    }
+
+    /// Identity of the node's task for deduplication; see `Task::fingerprint`.
+    ///
+    /// With a fallback, both tasks need a fingerprint.
+    pub fn fingerprint(&self) -> Option<String> {
+        let primary = self.task.fingerprint()?;
+        match &self.fallback {
+            Some(fallback) => Some(format!("{}|fallback:{}", primary, fallback.fingerprint()?)),
+            None => Some(primary),
+        }
+    }
+
+    /// Kind of task this node runs, e.g. `"shell"` or `"python"`.
+    pub fn task_type(&self) -> &'static str {
+        self.task.type_name()
+    }
+
+    /// Parameters of this node's task, as used by exporters.
+    pub fn task_params(&self) -> BTreeMap<String, String> {
+        self.task.params()
+    }
+
+    /// Whether the program this node's task starts is available; see
+    /// `Task::executable_found`.
+    pub fn executable_found(&self) -> bool {
+        self.task.executable_found()
     }
 }
 
@@ -47,6 +484,19 @@ impl Clone for TaskNode {
             id: self.id.clone(),
             name: self.name.clone(),
             task: self.task.clone(),
+            cost: self.cost,
+            retries: self.retries,
+            priority: self.priority,
+            join_policy: self.join_policy,
+            estimated_duration: self.estimated_duration,
+            timeout: self.timeout,
+            delay: self.delay,
+            file_inputs: self.file_inputs.clone(),
+            file_outputs: self.file_outputs.clone(),
+            fallback: self.fallback.clone(),
+            group: self.group.clone(),
+            resource: self.resource.clone(),
+            sequence: self.sequence,
         }
     }
 }
@@ -57,30 +507,192 @@ impl Debug for TaskNode {
     }
 }
 
+/// Callback for `Dag::visit`.
+pub trait NodeVisitor {
+    fn visit(&mut self, node: &TaskNode);
+}
+
+/// Order in which ready nodes are taken during a topological sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortStrategy {
+    /// Breadth-first: ready nodes run in the order they became ready.
+    #[default]
+    KahnBfs,
+    /// Depth-first: the most recently readied node runs next, so chains stay contiguous.
+    DepthFirst,
+    /// Highest `priority` first, ties broken by the order nodes became ready.
+    PriorityFirst,
+    /// Longest estimated path to the end of the DAG first, so the critical path is never
+    /// held up by work that has slack; see `TaskNode::with_estimated_duration`.
+    CriticalPathFirst,
+    /// Takes ready nodes from each `TaskNode::with_group` group in turn, so one group
+    /// with many ready nodes cannot starve the others. Ungrouped nodes form one more
+    /// group, and within a group ready nodes run in the order they became ready.
+    GroupRoundRobin,
+    /// The ready node that was added to the DAG first runs first; see
+    /// `TaskNode::sequence`.
+    InsertionOrder,
+}
+
+/// Set of ready nodes, popped according to a `SortStrategy`.
+enum Frontier<'a> {
+    Queue(VecDeque<&'a TaskNode>),
+    Stack(Vec<&'a TaskNode>),
+    /// Largest rank first, ties broken by insertion order.
+    Heap {
+        heap: BinaryHeap<(u128, Reverse<usize>)>,
+        nodes: Vec<&'a TaskNode>,
+        rank: Box<dyn Fn(&TaskNode) -> u128 + 'a>,
+    },
+    /// One queue per group, in the order groups were first seen; `next` is the queue
+    /// whose turn it is.
+    Groups {
+        queues: Vec<(Option<&'a str>, VecDeque<&'a TaskNode>)>,
+        next: usize,
+    },
+}
+
+impl<'a> Frontier<'a> {
+    fn new(dag: &'a Dag, strategy: SortStrategy) -> Result<Self, String> {
+        let heap = |rank: Box<dyn Fn(&TaskNode) -> u128 + 'a>| Frontier::Heap {
+            heap: BinaryHeap::new(),
+            nodes: Vec::new(),
+            rank,
+        };
+        Ok(match strategy {
+            SortStrategy::KahnBfs => Frontier::Queue(VecDeque::new()),
+            SortStrategy::DepthFirst => Frontier::Stack(Vec::new()),
+            // Shift so that negative priorities still order below positive ones
+            SortStrategy::PriorityFirst => heap(Box::new(|node| {
+                (node.priority() as i64 - i32::MIN as i64) as u128
+            })),
+            SortStrategy::CriticalPathFirst => {
+                let remaining = dag.remaining_path_lengths(|_| false)?;
+                heap(Box::new(move |node| remaining[node].as_nanos()))
+            }
+            SortStrategy::GroupRoundRobin => Frontier::Groups {
+                queues: Vec::new(),
+                next: 0,
+            },
+            SortStrategy::InsertionOrder => heap(Box::new(|node| {
+                (u64::MAX - node.sequence().unwrap_or(u64::MAX)) as u128
+            })),
+        })
+    }
+
+    /// Adds nodes that became ready together, in edge order.
+    fn extend(&mut self, ready: Vec<&'a TaskNode>) {
+        match self {
+            Frontier::Queue(queue) => queue.extend(ready),
+            // Push in reverse so the first ready node is popped first
+            Frontier::Stack(stack) => stack.extend(ready.into_iter().rev()),
+            Frontier::Heap { heap, nodes, rank } => {
+                for node in ready {
+                    heap.push((rank(node), Reverse(nodes.len())));
+                    nodes.push(node);
+                }
+            }
+            Frontier::Groups { queues, .. } => {
+                for node in ready {
+                    let group = node.group();
+                    match queues.iter_mut().find(|(key, _)| *key == group) {
+                        Some((_, queue)) => queue.push_back(node),
+                        None => queues.push((group, VecDeque::from([node]))),
+                    }
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<&'a TaskNode> {
+        match self {
+            Frontier::Queue(queue) => queue.pop_front(),
+            Frontier::Stack(stack) => stack.pop(),
+            Frontier::Heap { heap, nodes, .. } => heap.pop().map(|(_, Reverse(seq))| nodes[seq]),
+            Frontier::Groups { queues, next } => {
+                let count = queues.len();
+                (0..count).find_map(|offset| {
+                    let index = (*next + offset) % count;
+                    let node = queues[index].1.pop_front()?;
+                    *next = index + 1;
+                    Some(node)
+                })
+            }
+        }
+    }
+}
+
 pub struct Dag {
     name: String,
+    context: Context,
     node_table: HashMap<TaskNode, Vec<TaskNode>>,
     reverse_table: HashMap<TaskNode, Vec<TaskNode>>,
     indegree: HashMap<TaskNode, usize>,
+    teardown: Vec<TaskNode>,
+    default_task: Option<TaskFactory>,
+    max_depth: Option<usize>,
+    /// Ids of the nodes turned off with `set_enabled`.
+    disabled: HashSet<String>,
+    /// Sequence the next newly added node gets; see `TaskNode::sequence`.
+    next_sequence: u64,
 }
 
+/// Creates the task of a node that is only known by name; see `Dag::with_default_task`.
+pub type TaskFactory = Arc<dyn Fn() -> Arc<dyn Task> + Send + Sync>;
+
 impl Dag {
     pub fn new(name: String) -> Self {
         Dag {
             name,
+            context: Context::new(),
             node_table: HashMap::new(),
             reverse_table: HashMap::new(),
             indegree: HashMap::new(),
+            teardown: Vec::new(),
+            default_task: None,
+            max_depth: None,
+            disabled: HashSet::new(),
+            next_sequence: 0,
         }
     }
 
+    /// Gives nodes created by `add_relation_by_name` a task from `factory`.
+    pub fn with_default_task<F>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Arc<dyn Task> + Send + Sync + 'static,
+    {
+        self.default_task = Some(Arc::new(factory));
+        self
+    }
+
+    /// Limits how many tasks deep a chain may get through `try_add_task_relation`
+    ///
+    /// Edges added with `add_task_relation` are not checked.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
     pub fn add_task(&mut self, task_node: TaskNode) {
+        let task_node = self.sequenced(task_node);
         self.node_table.insert(task_node.clone(), Vec::new());
         self.reverse_table.insert(task_node.clone(), Vec::new());
         self.indegree.insert(task_node, 0);
     }
 
+    /// Adds a task and returns a handle to it, for wiring up edges later.
+    pub fn add_task_ref(&mut self, task_node: TaskNode) -> TaskNode {
+        self.add_task(task_node.clone());
+        task_node
+    }
+
     pub fn add_task_relation(&mut self, from: TaskNode, to: TaskNode) {
+        let from = self.sequenced(from);
+        let to = self.sequenced(to);
         if !self.node_table.contains_key(&from) {
             self.node_table.insert(from.clone(), Vec::new());
             self.reverse_table.insert(from.clone(), Vec::new());
@@ -95,12 +707,12 @@ impl Dag {
 
         self.node_table
             .entry(from.clone())
-            .or_insert(Vec::new())
+            .or_default()
             .push(to.clone());
 
         self.reverse_table
             .entry(to.clone())
-            .or_insert(Vec::new())
+            .or_default()
             .push(from.clone());
 
         self.indegree
@@ -109,113 +721,1109 @@ impl Dag {
             .or_insert(1);
     }
 
-    pub fn get_all_tasks(&self) -> Vec<TaskNode> {
-        self.node_table.keys().cloned().collect()
-    }
-
-    /// Topological sort of the DAG
-    ///
-    /// Uses Kahn's algorithm to perform a topological sort on the DAG.
-    /// First finds all nodes with an indegree of 0 and adds them to the queue.
-    /// Then, iteratively removes nodes from the queue, reducing the indegree of their neighbors.
-    /// If a neighbor's indegree reaches 0, it is added to the queue.
-    /// Until the queue is empty, the nodes are added to the result vector.
+    /// Like `add_task_relation`, rejecting an edge that would close a cycle or make the
+    /// longest chain through it deeper than `with_max_depth` allows
     ///
-    /// Returns a vector of nodes in topological order.
-    pub fn resolve_execution_order(&self) -> Result<Vec<TaskNode>, String> {
-        if self.node_table.is_empty() {
-            return Err("No nodes found".into());
+    /// Depth counts the tasks on a chain, so a lone node is 1 deep. The error names the
+    /// chain that would get too deep. The graph is left unchanged on error.
+    pub fn try_add_task_relation(&mut self, from: TaskNode, to: TaskNode) -> Result<(), String> {
+        if from == to || self.ancestors(&from).contains(&to) {
+            return Err(format!(
+                "edge '{}' -> '{}' would create a cycle",
+                from.name, to.name
+            ));
         }
+        if let Some(max_depth) = self.max_depth {
+            let chain = self.longest_chain_through(&from, &to)?;
+            if chain.len() > max_depth {
+                let names: Vec<&str> = chain.iter().map(|node| node.name.as_str()).collect();
+                return Err(format!(
+                    "edge '{}' -> '{}' would make the chain {} {} tasks deep, more than the limit of {}",
+                    from.name,
+                    to.name,
+                    names.join(" -> "),
+                    chain.len(),
+                    max_depth
+                ));
+            }
+        }
+        self.add_task_relation(from, to);
+        Ok(())
+    }
 
-        let mut result: Vec<TaskNode> = Vec::with_capacity(self.get_all_tasks().len());
-
-        // Initialize indegree map
-        let mut indegree: HashMap<&TaskNode, usize> = self
-            .indegree
-            .iter()
-            .map(|(node, &deg)| (node, deg))
-            .collect();
+    /// Longest chain that an edge `from -> to` would be part of: the longest chain
+    /// ending at `from` followed by the longest one starting at `to`.
+    fn longest_chain_through<'a>(
+        &'a self,
+        from: &'a TaskNode,
+        to: &'a TaskNode,
+    ) -> Result<Vec<&'a TaskNode>, String> {
+        let mut order: Vec<&TaskNode> = Vec::new();
+        if !self.node_table.is_empty() {
+            for node in self.resolve_execution_order()? {
+                order.push(
+                    self.node_table
+                        .get_key_value(&node)
+                        .expect("sorted nodes are in the DAG")
+                        .0,
+                );
+            }
+        }
+        // For each node, the length of its longest chain in one direction and the
+        // neighbor that chain continues through
+        type Chains<'a> = HashMap<&'a TaskNode, (usize, Option<&'a TaskNode>)>;
+        let longest = |nodes: &[&'a TaskNode],
+                       next: fn(&'a Dag, &'a TaskNode) -> &'a [TaskNode]| {
+            let mut chains: Chains<'a> = HashMap::new();
+            for &node in nodes {
+                let best = next(self, node)
+                    .iter()
+                    .max_by_key(|neighbor| chains.get(neighbor).map_or(0, |(len, _)| *len));
+                let len = best.map_or(0, |neighbor| chains[neighbor].0);
+                chains.insert(node, (len + 1, best));
+            }
+            chains
+        };
+        let into = longest(&order, Dag::predecessors);
+        order.reverse();
+        let out = longest(&order, Dag::successors);
 
-        // Find all nodes with an indegree of 0 and add them to the queue
-        let mut queue: VecDeque<&TaskNode> = indegree
-            .iter()
-            .filter_map(|(&node, &deg)| if deg == 0 { Some(node) } else { None })
-            .collect();
+        let mut chain = vec![from];
+        while let Some(&(_, Some(previous))) = into.get(chain[chain.len() - 1]) {
+            chain.push(previous);
+        }
+        chain.reverse();
+        chain.push(to);
+        while let Some(&(_, Some(next))) = out.get(chain[chain.len() - 1]) {
+            chain.push(next);
+        }
+        Ok(chain)
+    }
 
-        // Start topological sort
-        while let Some(current_node) = queue.pop_front() {
-            // Clone only when pushing into the final result
-            result.push(current_node.clone());
-            if let Some(neighbors) = self.node_table.get(current_node) {
-                for neighbor in neighbors {
-                    // Remove current node (indegree = 0) and update indegree count
-                    if let Some(d) = indegree.get_mut(neighbor) {
-                        if *d > 0 {
-                            // Decrement indegree count (cause we removed current node)
-                            *d -= 1;
-                            if *d == 0 {
-                                // Add node to queue if indegree count reaches zero
-                                queue.push_back(neighbor);
-                            }
-                        }
-                    }
-                }
+    /// Wires `from -> middle -> to`, replacing a direct `from -> to` edge if there is one
+    ///
+    /// Handy for injecting a validation or transform step into an existing pipeline.
+    /// Every copy of the direct edge is removed, and nodes missing from the DAG are
+    /// added as with `add_task_relation`.
+    pub fn insert_between(&mut self, from: TaskNode, to: TaskNode, middle: TaskNode) {
+        let mut removed = 0;
+        if let Some(targets) = self.node_table.get_mut(&from) {
+            let before = targets.len();
+            targets.retain(|target| *target != to);
+            removed = before - targets.len();
+        }
+        if removed > 0 {
+            if let Some(sources) = self.reverse_table.get_mut(&to) {
+                sources.retain(|source| *source != from);
+            }
+            if let Some(degree) = self.indegree.get_mut(&to) {
+                *degree -= removed;
             }
         }
+        self.add_task_relation(from, middle.clone());
+        self.add_task_relation(middle, to);
+    }
 
-        if result.len() != indegree.len() {
-            return Err("Graph has at least one cycle".into());
+    /// `node` with its `sequence` set: the one of the same node already in this DAG, or
+    /// else the next one, so two nodes added to the same DAG never share one.
+    fn sequenced(&mut self, mut node: TaskNode) -> TaskNode {
+        let existing = self
+            .node_table
+            .get_key_value(&node)
+            .and_then(|(existing, _)| existing.sequence);
+        node.sequence = Some(existing.unwrap_or_else(|| {
+            self.next_sequence += 1;
+            self.next_sequence - 1
+        }));
+        node
+    }
+
+    /// Adds a node of the DAG this one is derived from, keeping its sequence so the
+    /// copy runs in the same insertion order.
+    fn add_copied_task(&mut self, task_node: TaskNode) {
+        if let Some(sequence) = task_node.sequence {
+            self.next_sequence = self.next_sequence.max(sequence + 1);
         }
+        self.node_table.insert(task_node.clone(), Vec::new());
+        self.reverse_table.insert(task_node.clone(), Vec::new());
+        self.indegree.insert(task_node, 0);
+    }
 
-        Ok(result)
+    /// Adds an edge between the nodes named `from` and `to`, creating either one that is
+    /// missing with a task from `default`.
+    pub fn add_relation_auto(&mut self, from: &str, to: &str, default: impl Fn() -> Arc<dyn Task>) {
+        let mut node_named = |name: &str| match self
+            .node_table
+            .keys()
+            .find(|node| node.name == name)
+        {
+            Some(node) => node.clone(),
+            None => {
+                let node =
+                    TaskNode::from_parts(uuid::Uuid::new_v4().to_string(), name.into(), default());
+                self.add_task_ref(node)
+            }
+        };
+        let from = node_named(from);
+        let to = node_named(to);
+        self.add_task_relation(from, to);
     }
 
-    pub fn execute(&self) -> Result<(), String> {
-        self.resolve_execution_order()?
-            .into_iter()
-            .try_for_each(|node| node.execute())
+    /// Like `add_relation_auto`, creating missing nodes with the default task
+    ///
+    /// Fails without changing the graph when a node is missing and no default task was
+    /// set with `with_default_task`.
+    pub fn add_relation_by_name(&mut self, from: &str, to: &str) -> Result<(), String> {
+        let default = self.default_task.clone();
+        if default.is_none()
+            && let Some(missing) = [from, to]
+                .into_iter()
+                .find(|&name| !self.node_table.keys().any(|node| node.name == name))
+        {
+            return Err(format!(
+                "no task named '{}' and the DAG has no default task",
+                missing
+            ));
+        }
+        self.add_relation_auto(from, to, || {
+            default.as_ref().expect("missing nodes need a default task")()
+        });
+        Ok(())
     }
-}
 
-impl Debug for Dag {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Dag(name={}, nodes={:?})", self.name, self.node_table)
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
 
-    fn ok_task() -> Result<(), String> {
-        Ok(())
+    /// Registers a cleanup task that runs after every execution, even a failed one.
+    ///
+    /// Teardown tasks are not part of the graph; they run in reverse registration order
+    /// and their failures are reported separately from the main run.
+    pub fn add_teardown(&mut self, task_node: TaskNode) {
+        self.teardown.push(task_node);
     }
-    fn err_task() -> Result<(), String> {
-        Err("boom".into())
+
+    pub fn teardown_tasks(&self) -> &[TaskNode] {
+        &self.teardown
     }
 
-    #[test]
-    fn test_node_execute_ok() {
-        let n = TaskNode::new("ok_task".to_string(), ok_task);
-        assert_eq!(n.execute().unwrap(), ());
+    /// Context shared by the tasks of this DAG; clone it into tasks that exchange data.
+    pub fn context(&self) -> &Context {
+        &self.context
     }
 
-    #[test]
-    fn test_node_execute_err() {
-        let n = TaskNode::new("err_task".to_string(), err_task);
-        assert!(n.execute().is_err());
+    pub fn get_all_tasks(&self) -> Vec<TaskNode> {
+        self.node_table.keys().cloned().collect()
     }
 
-    #[test]
-    fn test_node_ops() {
-        let mut dag = Dag::new("g".to_string());
-        let n = TaskNode::new("n".to_string(), ok_task);
-        dag.add_task(n.clone());
-        let nodes = dag.get_all_tasks();
-        assert_eq!(nodes.len(), 1);
+    /// Direct downstream nodes of `node`, in the order the edges were added.
+    pub fn successors(&self, node: &TaskNode) -> &[TaskNode] {
+        self.node_table.get(node).map_or(&[], Vec::as_slice)
     }
 
-    #[test]
+    /// Direct upstream nodes of `node`, in the order the edges were added.
+    pub fn predecessors(&self, node: &TaskNode) -> &[TaskNode] {
+        self.reverse_table.get(node).map_or(&[], Vec::as_slice)
+    }
+
+    /// Enables or disables `node` without changing the graph
+    ///
+    /// A disabled node is treated as an instant success during execution: its task does
+    /// not run, but its dependents still run once it is reached.
+    pub fn set_enabled(&mut self, node: &TaskNode, enabled: bool) {
+        if enabled {
+            self.disabled.remove(&node.id);
+        } else if self.node_table.contains_key(node) {
+            self.disabled.insert(node.id.clone());
+        }
+    }
+
+    /// Whether `node`'s task runs; disabled nodes count as instant successes.
+    pub fn is_enabled(&self, node: &TaskNode) -> bool {
+        !self.disabled.contains(&node.id)
+    }
+
+    /// Checks that the internal tables agree with each other
+    ///
+    /// Every node must appear in all three tables, its stored indegree must equal the
+    /// number of its reverse edges, and every forward edge must have a matching reverse
+    /// edge. Executors never mutate these tables, so a DAG stays consistent across runs.
+    pub fn verify_consistent(&self) -> Result<(), String> {
+        for (node, targets) in &self.node_table {
+            let upstream = self
+                .reverse_table
+                .get(node)
+                .ok_or_else(|| format!("Task '{}' is missing from the reverse table", node.name))?;
+            let indegree = self.indegree.get(node).ok_or_else(|| {
+                format!("Task '{}' is missing from the indegree table", node.name)
+            })?;
+            if *indegree != upstream.len() {
+                return Err(format!(
+                    "Task '{}' has indegree {} but {} upstream edges",
+                    node.name,
+                    indegree,
+                    upstream.len()
+                ));
+            }
+            for target in targets {
+                let forward = targets.iter().filter(|t| *t == target).count();
+                let reverse = self
+                    .predecessors(target)
+                    .iter()
+                    .filter(|from| *from == node)
+                    .count();
+                if forward != reverse {
+                    return Err(format!(
+                        "Edge '{}' -> '{}' has no matching reverse edge",
+                        node.name, target.name
+                    ));
+                }
+            }
+        }
+
+        if self.reverse_table.len() != self.node_table.len()
+            || self.indegree.len() != self.node_table.len()
+        {
+            return Err("Node tables have different sizes".into());
+        }
+
+        Ok(())
+    }
+
+    /// Every `(from, to)` edge of the graph.
+    pub fn edges(&self) -> impl Iterator<Item = (&TaskNode, &TaskNode)> {
+        self.node_table
+            .iter()
+            .flat_map(|(from, targets)| targets.iter().map(move |to| (from, to)))
+    }
+
+    /// Whether `other` has the same nodes, edges and teardown tasks, compared by name
+    ///
+    /// Ids, tasks, node settings and the DAG names are ignored. Nodes and edges are
+    /// compared as multisets of names and `(from, to)` name pairs, so nodes sharing a
+    /// name are interchangeable: each name must occur equally often in both DAGs, and
+    /// so must each edge between two names. Teardown tasks are compared by name in
+    /// order.
+    pub fn structurally_eq(&self, other: &Dag) -> bool {
+        type Shape<'a> = (
+            BTreeMap<&'a str, usize>,
+            BTreeMap<(&'a str, &'a str), usize>,
+            Vec<&'a str>,
+        );
+        fn counts<T: Ord>(items: impl Iterator<Item = T>) -> BTreeMap<T, usize> {
+            let mut counts = BTreeMap::new();
+            for item in items {
+                *counts.entry(item).or_insert(0) += 1;
+            }
+            counts
+        }
+        fn shape(dag: &Dag) -> Shape<'_> {
+            (
+                counts(dag.node_table.keys().map(|node| node.name.as_str())),
+                counts(
+                    dag.edges()
+                        .map(|(from, to)| (from.name.as_str(), to.name.as_str())),
+                ),
+                dag.teardown.iter().map(|node| node.name.as_str()).collect(),
+            )
+        }
+        shape(self) == shape(other)
+    }
+
+    /// Rough number of bytes held by the node, edge and indegree tables
+    ///
+    /// Counts each stored `TaskNode` with its id and name strings, plus the allocated
+    /// capacity of the edge lists. Hash table overhead and the tasks themselves are not
+    /// counted, so treat the result as a lower bound for sizing, not an exact figure.
+    pub fn approx_size_bytes(&self) -> usize {
+        fn node_size(node: &TaskNode) -> usize {
+            size_of::<TaskNode>() + node.id.capacity() + node.name.capacity()
+        }
+        fn table_size(table: &HashMap<TaskNode, Vec<TaskNode>>) -> usize {
+            table
+                .iter()
+                .map(|(node, edges)| {
+                    node_size(node)
+                        + size_of::<Vec<TaskNode>>()
+                        + edges.capacity() * size_of::<TaskNode>()
+                        + edges
+                            .iter()
+                            .map(|n| n.id.capacity() + n.name.capacity())
+                            .sum::<usize>()
+                })
+                .sum()
+        }
+
+        let indegree: usize = self
+            .indegree
+            .keys()
+            .map(|node| node_size(node) + size_of::<usize>())
+            .sum();
+        table_size(&self.node_table) + table_size(&self.reverse_table) + indegree
+    }
+
+    /// All nodes reachable from `node` by following edges forwards, nearest first.
+    ///
+    /// The walk is iterative, so arbitrarily deep chains cannot overflow the stack.
+    pub fn descendants(&self, node: &TaskNode) -> Vec<TaskNode> {
+        self.walk(node, |current| self.successors(current))
+    }
+
+    /// All nodes `node` transitively depends on, nearest first.
+    ///
+    /// The walk is iterative, so arbitrarily deep chains cannot overflow the stack.
+    pub fn ancestors(&self, node: &TaskNode) -> Vec<TaskNode> {
+        self.walk(node, |current| self.predecessors(current))
+    }
+
+    /// Nodes that read one of the `changed` files, plus everything downstream of them
+    ///
+    /// These are the nodes a change to those files triggers. Paths are compared as
+    /// given, without resolving them on disk. Nodes are listed in execution order.
+    pub fn affected_by(&self, changed: &[PathBuf]) -> Vec<TaskNode> {
+        let mut affected: HashSet<TaskNode> = HashSet::new();
+        for node in self.node_table.keys() {
+            if affected.contains(node)
+                || !node.file_inputs().iter().any(|path| changed.contains(path))
+            {
+                continue;
+            }
+            affected.extend(self.descendants(node));
+            affected.insert(node.clone());
+        }
+
+        self.resolve_execution_order()
+            .unwrap_or_else(|_| self.get_all_tasks())
+            .into_iter()
+            .filter(|node| affected.contains(node))
+            .collect()
+    }
+
+    /// Whether `a` transitively depends on `b`, i.e. `b` is an ancestor of `a`.
+    ///
+    /// A node does not depend on itself. The search stops as soon as `b` is found.
+    pub fn depends_on(&self, a: &TaskNode, b: &TaskNode) -> bool {
+        let mut visited: HashSet<&TaskNode> = HashSet::from([a]);
+        let mut queue: VecDeque<&TaskNode> = VecDeque::from([a]);
+
+        while let Some(current) = queue.pop_front() {
+            for upstream in self.predecessors(current) {
+                if upstream == b {
+                    return true;
+                }
+                if visited.insert(upstream) {
+                    queue.push_back(upstream);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Edges implied by longer paths, e.g. `a -> c` when `a -> b -> c` also exists.
+    ///
+    /// Removing every returned edge leaves the same set of reachable nodes; see
+    /// `transitive_reduction`. Edges are listed by source in execution order.
+    pub fn find_redundant_edges(&self) -> Vec<(TaskNode, TaskNode)> {
+        let nodes = self
+            .resolve_execution_order()
+            .unwrap_or_else(|_| self.get_all_tasks());
+        let mut redundant = Vec::new();
+
+        for from in &nodes {
+            let direct = self.successors(from);
+            // Everything reachable through at least one intermediate node
+            let indirect: HashSet<TaskNode> = direct
+                .iter()
+                .flat_map(|next| self.descendants(next))
+                .collect();
+            for to in direct {
+                if indirect.contains(to) {
+                    redundant.push((from.clone(), to.clone()));
+                }
+            }
+        }
+
+        redundant
+    }
+
+    /// Copy of the DAG without the edges reported by `find_redundant_edges`
+    ///
+    /// Every node stays reachable from the same nodes as before, so any execution order
+    /// of the reduced DAG is also valid for this one. Nodes share their tasks, context
+    /// and teardown list with the original.
+    pub fn transitive_reduction(&self) -> Dag {
+        let redundant: HashSet<(TaskNode, TaskNode)> =
+            self.find_redundant_edges().into_iter().collect();
+        let mut reduced = Dag {
+            name: self.name.clone(),
+            context: self.context.clone(),
+            node_table: HashMap::new(),
+            reverse_table: HashMap::new(),
+            indegree: HashMap::new(),
+            teardown: self.teardown.clone(),
+            default_task: self.default_task.clone(),
+            max_depth: self.max_depth,
+            disabled: self.disabled.clone(),
+            next_sequence: 0,
+        };
+
+        for node in self.node_table.keys() {
+            reduced.add_copied_task(node.clone());
+        }
+        for (from, to) in self.edges() {
+            if !redundant.contains(&(from.clone(), to.clone())) {
+                reduced.add_task_relation(from.clone(), to.clone());
+            }
+        }
+
+        reduced
+    }
+
+    /// Copy of the DAG with every node's task replaced by `f(node)`
+    ///
+    /// Nodes keep their ids and settings and the edges stay the same, so the copy can
+    /// run the same topology with mock tasks. Teardown tasks are mapped too.
+    pub fn map_tasks(&self, f: impl Fn(&TaskNode) -> Arc<dyn Task>) -> Dag {
+        self.map_nodes(|node| {
+            let mut copy = node.clone();
+            copy.task = f(node);
+            copy
+        })
+    }
+
+    /// Gives every node a deterministic id derived from its name and position
+    ///
+    /// The `n`th node added with a given name, counting from 0, gets the same id in
+    /// every process, as does the `n`th teardown task with a given name, so a DAG built
+    /// the same way exports identical plans on every run. Nodes without a sequence, or
+    /// sharing one, are ordered by depth, then by the names of their neighbors and their
+    /// task parameters; only nodes alike in all of these fall back to their old ids. The
+    /// ids differ from those of `TaskNode::new_hashed`. Handles to nodes taken before
+    /// this call keep the old ids and no longer refer to nodes of the DAG.
+    pub fn stabilize_ids(&mut self) {
+        let stable_id = |key: String| {
+            uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, key.as_bytes()).to_string()
+        };
+        // Longest chain of upstream nodes, the same for every topological order
+        let mut depth: HashMap<&TaskNode, usize> = HashMap::new();
+        for node in self.resolve_execution_order().unwrap_or_default() {
+            let below = self
+                .predecessors(&node)
+                .iter()
+                .map(|upstream| depth.get(upstream).map_or(0, |d| d + 1))
+                .max()
+                .unwrap_or(0);
+            let (key, _) = self
+                .node_table
+                .get_key_value(&node)
+                .expect("node of this DAG");
+            depth.insert(key, below);
+        }
+        fn neighbor_names(nodes: &[TaskNode]) -> Vec<&str> {
+            let mut names: Vec<&str> = nodes.iter().map(|node| node.name.as_str()).collect();
+            names.sort_unstable();
+            names
+        }
+        let mut ids: HashMap<&TaskNode, String> = HashMap::new();
+        let mut main: Vec<&TaskNode> = self.node_table.keys().collect();
+        main.sort_by_cached_key(|node| {
+            (
+                node.sequence.is_none(),
+                node.sequence,
+                depth.get(node).copied(),
+                neighbor_names(self.predecessors(node)),
+                neighbor_names(self.successors(node)),
+                node.task_params(),
+                node.id.clone(),
+            )
+        });
+        let mut seen: HashMap<(&str, &str), usize> = HashMap::new();
+        let groups = main
+            .into_iter()
+            .map(|node| ("task", node))
+            .chain(self.teardown.iter().map(|node| ("teardown", node)));
+        for (kind, node) in groups {
+            let count = seen.entry((kind, &node.name)).or_insert(0);
+            ids.insert(
+                node,
+                stable_id(format!("stable:{}:{}#{}", kind, node.name, count)),
+            );
+            *count += 1;
+        }
+        *self = self.map_nodes(|node| node.clone().with_id(ids[node].clone()));
+    }
+
+    /// Copy of the DAG with every node, teardown tasks included, replaced by `f(node)`
+    /// and the edges kept between the replacements.
+    fn map_nodes(&self, f: impl Fn(&TaskNode) -> TaskNode) -> Dag {
+        let mapped: HashMap<&TaskNode, TaskNode> = self
+            .node_table
+            .keys()
+            .chain(&self.teardown)
+            .map(|node| (node, f(node)))
+            .collect();
+        let remap = |nodes: &[TaskNode]| -> Vec<TaskNode> {
+            nodes.iter().map(|node| mapped[node].clone()).collect()
+        };
+        let table = |table: &HashMap<TaskNode, Vec<TaskNode>>| {
+            table
+                .iter()
+                .map(|(node, neighbors)| (mapped[node].clone(), remap(neighbors)))
+                .collect()
+        };
+
+        Dag {
+            name: self.name.clone(),
+            context: self.context.clone(),
+            node_table: table(&self.node_table),
+            reverse_table: table(&self.reverse_table),
+            indegree: self
+                .indegree
+                .iter()
+                .map(|(node, &degree)| (mapped[node].clone(), degree))
+                .collect(),
+            teardown: remap(&self.teardown),
+            default_task: self.default_task.clone(),
+            max_depth: self.max_depth,
+            disabled: mapped
+                .iter()
+                .filter(|(node, _)| self.disabled.contains(&node.id))
+                .map(|(_, copy)| copy.id.clone())
+                .collect(),
+            next_sequence: self.next_sequence,
+        }
+    }
+
+    /// Splits the DAG into its weakly connected components, each a standalone DAG
+    ///
+    /// Two nodes share a component when an edge, in either direction, leads from one to
+    /// the other. Components are named `{name}-{n}` counting from 1 and ordered by their
+    /// first node in execution order. They share tasks, context and the default task
+    /// with this DAG; teardown tasks belong to the whole DAG and are left out.
+    pub fn connected_components(&self) -> Vec<Dag> {
+        let nodes = self.resolve_execution_order().unwrap_or_else(|_| {
+            let mut nodes = self.get_all_tasks();
+            nodes.sort_by(|a, b| a.name.cmp(&b.name));
+            nodes
+        });
+        let mut component_of: HashMap<&TaskNode, usize> = HashMap::new();
+        let mut components: Vec<Dag> = Vec::new();
+
+        for start in &nodes {
+            if component_of.contains_key(start) {
+                continue;
+            }
+            let index = components.len();
+            let mut component = Dag {
+                name: format!("{}-{}", self.name, index + 1),
+                context: self.context.clone(),
+                node_table: HashMap::new(),
+                reverse_table: HashMap::new(),
+                indegree: HashMap::new(),
+                teardown: Vec::new(),
+                default_task: self.default_task.clone(),
+                max_depth: self.max_depth,
+                disabled: self.disabled.clone(),
+                next_sequence: 0,
+            };
+            component_of.insert(start, index);
+            component.add_copied_task(start.clone());
+            let mut queue: VecDeque<&TaskNode> = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                for neighbor in self
+                    .successors(current)
+                    .iter()
+                    .chain(self.predecessors(current))
+                {
+                    if component_of.insert(neighbor, index).is_none() {
+                        component.add_copied_task(neighbor.clone());
+                        queue.push_back(neighbor);
+                    }
+                }
+                for next in self.successors(current) {
+                    component.add_task_relation(current.clone(), next.clone());
+                }
+            }
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Every group of nodes that depend on each other in a cycle
+    ///
+    /// Returns the strongly connected components with more than one node, found with
+    /// Tarjan's algorithm. Each group is sorted by name and the groups by their first
+    /// node, so the result is stable between runs. See `describe_cycles` for a summary
+    /// fit for error messages.
+    pub fn find_all_cycles(&self) -> Vec<Vec<TaskNode>> {
+        let mut index: HashMap<&TaskNode, usize> = HashMap::new();
+        let mut lowlink: HashMap<&TaskNode, usize> = HashMap::new();
+        let mut on_stack: HashSet<&TaskNode> = HashSet::new();
+        let mut stack: Vec<&TaskNode> = Vec::new();
+        let mut cycles: Vec<Vec<TaskNode>> = Vec::new();
+
+        for start in self.node_table.keys() {
+            if index.contains_key(start) {
+                continue;
+            }
+            // Explicit call stack of (node, next successor to look at), so deep graphs
+            // cannot overflow the thread's stack
+            let mut calls: Vec<(&TaskNode, usize)> = vec![(start, 0)];
+            index.insert(start, index.len());
+            lowlink.insert(start, index[start]);
+            stack.push(start);
+            on_stack.insert(start);
+
+            while let Some(&mut (node, ref mut next)) = calls.last_mut() {
+                if let Some(successor) = self.successors(node).get(*next) {
+                    *next += 1;
+                    if !index.contains_key(successor) {
+                        index.insert(successor, index.len());
+                        lowlink.insert(successor, index[successor]);
+                        stack.push(successor);
+                        on_stack.insert(successor);
+                        calls.push((successor, 0));
+                    } else if on_stack.contains(successor) {
+                        let low = lowlink[node].min(index[successor]);
+                        lowlink.insert(node, low);
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some(&(caller, _)) = calls.last() {
+                    let low = lowlink[caller].min(lowlink[node]);
+                    lowlink.insert(caller, low);
+                }
+                if lowlink[node] == index[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack.remove(member);
+                        component.push(member.clone());
+                        if member == node {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 {
+                        component.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+                        cycles.push(component);
+                    }
+                }
+            }
+        }
+
+        cycles.sort_by(|a, b| (&a[0].name, &a[0].id).cmp(&(&b[0].name, &b[0].id)));
+        cycles
+    }
+
+    /// One line per group from `find_all_cycles`, e.g. `cycle of 2 tasks: a, b`
+    ///
+    /// Groups larger than `MAX_CYCLE_NAMES` list only their first names. Empty when the
+    /// graph has no cycles.
+    pub fn describe_cycles(&self) -> String {
+        self.find_all_cycles()
+            .iter()
+            .map(|cycle| {
+                let names: Vec<&str> = cycle
+                    .iter()
+                    .take(MAX_CYCLE_NAMES)
+                    .map(|node| node.name.as_str())
+                    .collect();
+                let mut line = format!("cycle of {} tasks: {}", cycle.len(), names.join(", "));
+                if cycle.len() > names.len() {
+                    line.push_str(&format!(" and {} more", cycle.len() - names.len()));
+                }
+                line + "\n"
+            })
+            .collect()
+    }
+
+    /// Breadth-first walk from `start`, excluding `start` itself.
+    fn walk<'a>(
+        &'a self,
+        start: &'a TaskNode,
+        next: impl Fn(&'a TaskNode) -> &'a [TaskNode],
+    ) -> Vec<TaskNode> {
+        let mut visited: HashSet<&TaskNode> = HashSet::from([start]);
+        let mut queue: VecDeque<&TaskNode> = VecDeque::from([start]);
+        let mut result = Vec::new();
+
+        while let Some(current) = queue.pop_front() {
+            for neighbor in next(current) {
+                if visited.insert(neighbor) {
+                    result.push(neighbor.clone());
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Topological sort of the DAG
+    ///
+    /// Uses Kahn's algorithm to perform a topological sort on the DAG.
+    /// First finds all nodes with an indegree of 0 and adds them to the queue.
+    /// Then, iteratively removes nodes from the queue, reducing the indegree of their neighbors.
+    /// If a neighbor's indegree reaches 0, it is added to the queue.
+    /// Until the queue is empty, the nodes are added to the result vector.
+    ///
+    /// Returns a vector of nodes in topological order.
+    pub fn resolve_execution_order(&self) -> Result<Vec<TaskNode>, String> {
+        self.resolve_execution_order_with(SortStrategy::KahnBfs)
+    }
+
+    /// Like `resolve_execution_order`, refusing DAGs with more than `max_nodes` nodes
+    ///
+    /// The count is checked before any sorting work or allocation, so a service can
+    /// sort untrusted graphs without risking unbounded memory or CPU use.
+    pub fn resolve_execution_order_bounded(
+        &self,
+        max_nodes: usize,
+    ) -> Result<Vec<TaskNode>, String> {
+        if self.node_table.len() > max_nodes {
+            return Err(format!(
+                "DAG has {} tasks, more than the limit of {}",
+                self.node_table.len(),
+                max_nodes
+            ));
+        }
+        self.resolve_execution_order()
+    }
+
+    /// Estimated time left until every node is done, given the nodes already `done`
+    ///
+    /// The longest chain of estimated durations through the unfinished nodes; nodes
+    /// without an estimate count as instant.
+    pub(crate) fn remaining_time(
+        &self,
+        done: impl Fn(&TaskNode) -> bool,
+    ) -> Result<Duration, String> {
+        Ok(self
+            .remaining_path_lengths(done)?
+            .into_values()
+            .max()
+            .unwrap_or_default())
+    }
+
+    /// Estimated time from the start of each node to the end of the DAG
+    ///
+    /// A node's length is its own estimate, zero once it is `done`, plus the longest
+    /// length among its successors.
+    fn remaining_path_lengths(
+        &self,
+        done: impl Fn(&TaskNode) -> bool,
+    ) -> Result<HashMap<&TaskNode, Duration>, String> {
+        let order = self.resolve_execution_order()?;
+        let mut lengths: HashMap<&TaskNode, Duration> = HashMap::with_capacity(order.len());
+        for node in order.iter().rev() {
+            let downstream = self
+                .successors(node)
+                .iter()
+                .map(|next| lengths[next])
+                .max()
+                .unwrap_or_default();
+            let own = if done(node) {
+                Duration::ZERO
+            } else {
+                node.estimated_duration().unwrap_or_default()
+            };
+            // Key by the DAG's own copy, which outlives this function
+            let (key, _) = self
+                .node_table
+                .get_key_value(node)
+                .expect("node is in the DAG");
+            lengths.insert(key, own + downstream);
+        }
+        Ok(lengths)
+    }
+
+    /// Nodes grouped into waves that could run at the same time
+    ///
+    /// A node's level is one more than the highest level among its upstream nodes, so
+    /// roots form the first wave. Within a level nodes keep their execution order.
+    pub fn execution_levels(&self) -> Result<Vec<Vec<TaskNode>>, String> {
+        let order = self.resolve_execution_order()?;
+        let mut level_of: HashMap<&TaskNode, usize> = HashMap::with_capacity(order.len());
+        let mut levels: Vec<Vec<TaskNode>> = Vec::new();
+        for node in &order {
+            let level = self
+                .predecessors(node)
+                .iter()
+                .map(|upstream| level_of[upstream] + 1)
+                .max()
+                .unwrap_or(0);
+            level_of.insert(node, level);
+            if level == levels.len() {
+                levels.push(Vec::new());
+            }
+            levels[level].push(node.clone());
+        }
+        Ok(levels)
+    }
+
+    /// Size of the widest execution level: the most tasks that can usefully run at once.
+    pub fn max_parallelism(&self) -> Result<usize, String> {
+        Ok(self
+            .execution_levels()?
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// Calls `visitor` on every node in execution order, without running any task.
+    pub fn visit<V: NodeVisitor>(&self, visitor: &mut V) -> Result<(), String> {
+        for node in &self.resolve_execution_order()? {
+            visitor.visit(node);
+        }
+        Ok(())
+    }
+
+    /// Names of the nodes in execution order.
+    pub fn execution_order_names(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .resolve_execution_order()?
+            .into_iter()
+            .map(|node| node.name)
+            .collect())
+    }
+
+    /// Execution order reversed: every node comes before the nodes it depends on.
+    ///
+    /// This is the order in which resources acquired by a run should be released.
+    pub fn reverse_execution_order(&self) -> Result<Vec<TaskNode>, String> {
+        let mut order = self.resolve_execution_order()?;
+        order.reverse();
+        Ok(order)
+    }
+
+    /// Topological sort of the DAG using the given strategy
+    ///
+    /// All strategies run Kahn's algorithm and only differ in which ready node
+    /// (indegree of 0) is taken next, so every one of them yields a valid topological order.
+    pub fn resolve_execution_order_with(
+        &self,
+        strategy: SortStrategy,
+    ) -> Result<Vec<TaskNode>, String> {
+        if self.node_table.is_empty() {
+            return Err("No nodes found".into());
+        }
+
+        let mut result: Vec<TaskNode> = Vec::with_capacity(self.get_all_tasks().len());
+
+        // Initialize indegree map
+        let mut indegree: HashMap<&TaskNode, usize> = self
+            .indegree
+            .iter()
+            .map(|(node, &deg)| (node, deg))
+            .collect();
+
+        // Find all nodes with an indegree of 0 and add them to the frontier
+        let mut frontier = Frontier::new(self, strategy)?;
+        frontier.extend(
+            indegree
+                .iter()
+                .filter_map(|(&node, &deg)| if deg == 0 { Some(node) } else { None })
+                .collect(),
+        );
+
+        // Start topological sort
+        while let Some(current_node) = frontier.pop() {
+            // Clone only when pushing into the final result
+            result.push(current_node.clone());
+            let mut ready = Vec::new();
+            if let Some(neighbors) = self.node_table.get(current_node) {
+                for neighbor in neighbors {
+                    // Remove current node (indegree = 0) and update indegree count
+                    if let Some(d) = indegree.get_mut(neighbor)
+                        && *d > 0
+                    {
+                        // Decrement indegree count (cause we removed current node)
+                        *d -= 1;
+                        if *d == 0 {
+                            // Add node to frontier if indegree count reaches zero
+                            ready.push(neighbor);
+                        }
+                    }
+                }
+            }
+            frontier.extend(ready);
+        }
+
+        if result.len() != indegree.len() {
+            return Err("Graph has at least one cycle".into());
+        }
+
+        Ok(result)
+    }
+
+    pub fn execute(&self) -> Result<(), String> {
+        self.execute_with_observer(&NoopObserver)
+    }
+}
+
+impl Debug for Dag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dag(name={}, nodes={:?})", self.name, self.node_table)
+    }
+}
+
+/// Builds a `Dag` from edges between `TaskNode` bindings
+///
+/// `dag! { a => b, a => c, b => d }` adds the edges `a -> b`, `a -> c` and `b -> d`, cloning
+/// each binding, to a DAG named "dag". Name the DAG with `dag!("etl"; a => b)`.
+#[macro_export]
+macro_rules! dag {
+    ($name:expr; $($from:ident => $to:ident),* $(,)?) => {{
+        let mut dag = $crate::dag::Dag::new(::std::string::String::from($name));
+        $(dag.add_task_relation($from.clone(), $to.clone());)*
+        dag
+    }};
+    ($($from:ident => $to:ident),* $(,)?) => {
+        $crate::dag!("dag"; $($from => $to),*)
+    };
+}
+
+/// Builds a `Dag` from several threads at once
+///
+/// Every method takes `&self` and locks the graph for the length of one call, so a
+/// builder can be shared by reference between discovery threads. `finish` hands back
+/// the plain `Dag` once building is done.
+#[derive(Debug)]
+pub struct ConcurrentDagBuilder {
+    dag: Mutex<Dag>,
+}
+
+impl ConcurrentDagBuilder {
+    pub fn new(name: String) -> Self {
+        ConcurrentDagBuilder {
+            dag: Mutex::new(Dag::new(name)),
+        }
+    }
+
+    pub fn add_task(&self, task_node: TaskNode) {
+        self.lock().add_task(task_node);
+    }
+
+    pub fn add_task_relation(&self, from: TaskNode, to: TaskNode) {
+        self.lock().add_task_relation(from, to);
+    }
+
+    pub fn add_teardown(&self, task_node: TaskNode) {
+        self.lock().add_teardown(task_node);
+    }
+
+    pub fn finish(self) -> Dag {
+        self.dag
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Dag> {
+        self.dag
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok_task() -> Result<(), String> {
+        Ok(())
+    }
+    fn err_task() -> Result<(), String> {
+        Err("boom".into())
+    }
+
+    #[test]
+    fn test_node_execute_ok() {
+        let n = TaskNode::new("ok_task".to_string(), ok_task);
+        assert_eq!(n.execute().unwrap(), ());
+    }
+
+    #[test]
+    fn test_node_execute_err() {
+        let n = TaskNode::new("err_task".to_string(), err_task);
+        assert!(n.execute().is_err());
+    }
+
+    #[test]
+    fn test_node_new_hashed_is_stable() {
+        let a = TaskNode::new_hashed("extract".to_string(), ok_task);
+        let b = TaskNode::new_hashed("extract".to_string(), err_task);
+        let c = TaskNode::new_hashed("load".to_string(), ok_task);
+        assert_eq!(a.id(), b.id());
+        assert_ne!(a.id(), c.id());
+        assert_ne!(
+            TaskNode::new("extract".to_string(), ok_task).id(),
+            TaskNode::new("extract".to_string(), ok_task).id()
+        );
+    }
+
+    #[test]
+    fn test_node_task_type() {
+        let n = TaskNode::new("sh".to_string(), crate::task::ShellTask::new("true"));
+        assert_eq!(n.task_type(), "shell");
+        let n = TaskNode::new("ok_task".to_string(), ok_task);
+        assert_eq!(n.task_type(), "closure");
+    }
+
+    #[test]
+    fn test_node_execute_panic() {
+        let line = line!() + 2;
+        let n = TaskNode::new("panicky".to_string(), || -> Result<(), String> {
+            panic!("kaboom {}", 42)
+        });
+        let err = n.execute().unwrap_err();
+        let message = format!("task panicked: kaboom 42 (at src/dag.rs:{}:13)", line);
+        assert!(err.starts_with(&message), "{}", err);
+        let backtraces = std::env::var("RUST_BACKTRACE").is_ok_and(|value| value != "0");
+        assert_eq!(err.contains("\nbacktrace:\n"), backtraces);
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(n.clone(), TaskNode::new("after".to_string(), ok_task));
+        let expected = format!("Task 'panicky' failed: {}", message);
+        assert!(dag.execute().unwrap_err().starts_with(&expected));
+        assert!(dag.execute_parallel(2).unwrap_err().starts_with(&expected));
+        let (report, _) = dag.try_execute();
+        let crate::report::NodeStatus::Failed(reported) = report.status("panicky").unwrap() else {
+            panic!("the panicking node must fail");
+        };
+        assert!(reported.starts_with(&message));
+
+        // Panics behind a timeout are caught on the worker thread
+        let timed = n.with_timeout(Duration::from_secs(5));
+        assert!(timed.execute().unwrap_err().starts_with(&message));
+    }
+
+    #[test]
+    fn test_node_ops() {
+        let mut dag = Dag::new("g".to_string());
+        let n = TaskNode::new("n".to_string(), ok_task);
+        dag.add_task(n.clone());
+        let nodes = dag.get_all_tasks();
+        assert_eq!(nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_dag_name() {
+        let mut dag = Dag::new("g".to_string());
+        assert_eq!(dag.name(), "g");
+        dag.set_name("nightly".to_string());
+        assert_eq!(dag.name(), "nightly");
+        assert!(format!("{:?}", dag).starts_with("Dag(name=nightly"));
+    }
+
+    #[test]
     fn test_edge_ops() {
         let mut dag = Dag::new("g".to_string());
         let a = TaskNode::new("a".to_string(), ok_task);
@@ -226,6 +1834,58 @@ mod tests {
         assert_eq!(dag.node_table.get(&b).unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_then_chain() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), ok_task);
+        let b = TaskNode::new("b".to_string(), ok_task);
+        let c = TaskNode::new("c".to_string(), ok_task);
+        let last = a.then(&mut dag, b).then(&mut dag, c.clone());
+        assert_eq!(last, c);
+        let order = dag
+            .resolve_execution_order()
+            .unwrap()
+            .iter()
+            .map(|node| node.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_edges_of_diamond() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), ok_task);
+        let b = TaskNode::new("b".to_string(), ok_task);
+        let c = TaskNode::new("c".to_string(), ok_task);
+        let d = TaskNode::new("d".to_string(), ok_task);
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(a.clone(), c.clone());
+        dag.add_task_relation(b.clone(), d.clone());
+        dag.add_task_relation(c.clone(), d.clone());
+        assert_eq!(dag.edges().count(), 4);
+        assert_eq!(dag.edges().filter(|(from, _)| **from == a).count(), 2);
+        assert_eq!(dag.edges().filter(|(_, to)| **to == d).count(), 2);
+    }
+
+    #[test]
+    fn test_dag_runs_twice() {
+        let dag = branching_dag();
+        assert_eq!(dag.verify_consistent(), Ok(()));
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        assert_eq!(dag.verify_consistent(), Ok(()));
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(dag.resolve_execution_order().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_verify_consistent_detects_drift() {
+        let mut dag = branching_dag();
+        let node = dag.get_all_tasks().pop().unwrap();
+        dag.indegree.insert(node, 7);
+        assert!(dag.verify_consistent().is_err());
+    }
+
     #[test]
     fn test_dag_topo_sort_ok() {
         let mut dag = Dag::new("g".into());
@@ -272,4 +1932,855 @@ mod tests {
         let result = dag.execute();
         assert_eq!(result, Ok(()));
     }
+
+    fn assert_valid_order(dag: &Dag, order: &[TaskNode]) {
+        assert_eq!(order.len(), dag.get_all_tasks().len());
+        let position: HashMap<&TaskNode, usize> =
+            order.iter().enumerate().map(|(i, n)| (n, i)).collect();
+        for node in order {
+            for next in dag.successors(node) {
+                assert!(position[node] < position[next]);
+            }
+        }
+    }
+
+    fn branching_dag() -> Dag {
+        // root -> a1 -> a2 -> a3, root -> b1 -> b2, b2 -> a3
+        let mut dag = Dag::new("g".into());
+        let root = TaskNode::new("root".to_string(), ok_task);
+        let a1 = TaskNode::new("a1".to_string(), ok_task);
+        let a2 = TaskNode::new("a2".to_string(), ok_task);
+        let a3 = TaskNode::new("a3".to_string(), ok_task);
+        let b1 = TaskNode::new("b1".to_string(), ok_task).with_priority(5);
+        let b2 = TaskNode::new("b2".to_string(), ok_task);
+        dag.add_task_relation(root.clone(), a1.clone());
+        dag.add_task_relation(root.clone(), b1.clone());
+        dag.add_task_relation(a1.clone(), a2.clone());
+        dag.add_task_relation(a2.clone(), a3.clone());
+        dag.add_task_relation(b1.clone(), b2.clone());
+        dag.add_task_relation(b2.clone(), a3.clone());
+        dag
+    }
+
+    #[test]
+    fn test_sort_strategies_are_valid() {
+        let dag = branching_dag();
+        for strategy in [
+            SortStrategy::KahnBfs,
+            SortStrategy::DepthFirst,
+            SortStrategy::PriorityFirst,
+            SortStrategy::CriticalPathFirst,
+            SortStrategy::InsertionOrder,
+        ] {
+            let order = dag.resolve_execution_order_with(strategy).unwrap();
+            assert_valid_order(&dag, &order);
+        }
+    }
+
+    #[test]
+    fn test_sort_strategy_orders() {
+        let dag = branching_dag();
+        let names = |strategy| {
+            dag.resolve_execution_order_with(strategy)
+                .unwrap()
+                .iter()
+                .map(|node| node.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(SortStrategy::KahnBfs),
+            vec!["root", "a1", "b1", "a2", "b2", "a3"]
+        );
+        // The a-chain stays contiguous until it has to wait on b2
+        assert_eq!(
+            names(SortStrategy::DepthFirst),
+            vec!["root", "a1", "a2", "b1", "b2", "a3"]
+        );
+        assert_eq!(
+            names(SortStrategy::PriorityFirst),
+            vec!["root", "b1", "a1", "b2", "a2", "a3"]
+        );
+    }
+
+    #[test]
+    fn test_insertion_order_runs_independent_nodes_as_added() {
+        let mut dag = Dag::new("g".into());
+        let names = ["zeta", "alpha", "mid", "beta", "omega"];
+        for name in names {
+            dag.add_task(TaskNode::new(name.to_string(), ok_task));
+        }
+        // Wiring a node up again later keeps its original place
+        let last = TaskNode::new("last".to_string(), ok_task);
+        for node in dag.get_all_tasks() {
+            dag.add_task_relation(node, last.clone());
+        }
+
+        let order: Vec<String> = dag
+            .resolve_execution_order_with(SortStrategy::InsertionOrder)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.name)
+            .collect();
+        assert_eq!(order, ["zeta", "alpha", "mid", "beta", "omega", "last"]);
+        let sequences: Vec<Option<u64>> = dag
+            .resolve_execution_order_with(SortStrategy::InsertionOrder)
+            .unwrap()
+            .iter()
+            .map(|node| node.sequence())
+            .collect();
+        assert_eq!(sequences, (0..6).map(Some).collect::<Vec<_>>());
+        assert_eq!(last.sequence(), None);
+    }
+
+    #[test]
+    fn test_insertion_order_numbers_both_new_ends_of_an_edge() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), ok_task);
+        let b = TaskNode::new("b".to_string(), ok_task);
+        dag.add_task_relation(a, b);
+        dag.add_task(TaskNode::new("c".to_string(), ok_task));
+
+        let sequences: Vec<(String, Option<u64>)> = dag
+            .resolve_execution_order_with(SortStrategy::InsertionOrder)
+            .unwrap()
+            .into_iter()
+            .map(|node| (node.name.clone(), node.sequence()))
+            .collect();
+        assert_eq!(
+            sequences,
+            [
+                ("a".to_string(), Some(0)),
+                ("b".to_string(), Some(1)),
+                ("c".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insertion_order_renumbers_nodes_from_another_dag() {
+        let mut other = Dag::new("o".into());
+        let a = other.add_task_ref(TaskNode::new("a".to_string(), ok_task));
+        let a = other.get_all_tasks().into_iter().find(|n| *n == a).unwrap();
+        assert_eq!(a.sequence(), Some(0));
+
+        let mut dag = Dag::new("d".into());
+        dag.add_task(TaskNode::new("x".to_string(), ok_task));
+        dag.add_task(a);
+        let order: Vec<(String, Option<u64>)> = dag
+            .resolve_execution_order_with(SortStrategy::InsertionOrder)
+            .unwrap()
+            .into_iter()
+            .map(|node| (node.name.clone(), node.sequence()))
+            .collect();
+        assert_eq!(
+            order,
+            [("x".to_string(), Some(0)), ("a".to_string(), Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_structurally_eq_compares_names_and_edges() {
+        let build = |edges: &[(&str, &str)]| {
+            let mut dag = Dag::new("g".into());
+            let mut nodes: HashMap<String, TaskNode> = HashMap::new();
+            let mut node = |name: &str| {
+                nodes
+                    .entry(name.to_string())
+                    .or_insert_with(|| TaskNode::new(name.to_string(), ok_task))
+                    .clone()
+            };
+            for (from, to) in edges {
+                let (from, to) = (node(from), node(to));
+                dag.add_task_relation(from, to);
+            }
+            dag
+        };
+        let diamond = [("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")];
+        let mut reordered = diamond;
+        reordered.reverse();
+        assert!(build(&diamond).structurally_eq(&build(&reordered)));
+        assert!(build(&diamond).structurally_eq(&build(&diamond).transitive_reduction()));
+
+        assert!(!build(&diamond).structurally_eq(&build(&diamond[..3])));
+        let flipped = [("a", "b"), ("a", "c"), ("d", "b"), ("c", "d")];
+        assert!(!build(&diamond).structurally_eq(&build(&flipped)));
+        let mut extra = build(&diamond);
+        extra.add_task(TaskNode::new("e".to_string(), ok_task));
+        assert!(!build(&diamond).structurally_eq(&extra));
+
+        // Two nodes named "x" are not the same as one
+        let mut twice = Dag::new("g".into());
+        twice.add_task(TaskNode::new("x".to_string(), ok_task));
+        let mut once = Dag::new("g".into());
+        once.add_task(TaskNode::new("x".to_string(), ok_task));
+        assert!(twice.structurally_eq(&once));
+        twice.add_task(TaskNode::new("x".to_string(), ok_task));
+        assert!(!twice.structurally_eq(&once));
+    }
+
+    #[test]
+    fn test_bounded_sort_rejects_large_graphs() {
+        let dag = branching_dag();
+        assert_eq!(
+            dag.resolve_execution_order_bounded(5).unwrap_err(),
+            "DAG has 6 tasks, more than the limit of 5"
+        );
+        assert_eq!(dag.resolve_execution_order_bounded(6).unwrap().len(), 6);
+
+        // A cycle is only found by sorting, so the bound is reported first
+        let (a, b) = (
+            TaskNode::new("a".to_string(), ok_task),
+            TaskNode::new("b".to_string(), ok_task),
+        );
+        let mut cyclic = Dag::new("g".into());
+        cyclic.add_task_relation(a.clone(), b.clone());
+        cyclic.add_task_relation(b, a);
+        assert!(
+            cyclic
+                .resolve_execution_order_bounded(1)
+                .unwrap_err()
+                .contains("limit of 1")
+        );
+    }
+
+    #[test]
+    fn test_insert_between_replaces_the_direct_edge() {
+        let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+        let (extract, load, report) = (node("extract"), node("load"), node("report"));
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(extract.clone(), load.clone());
+        dag.add_task_relation(extract.clone(), report.clone());
+
+        let validate = node("validate");
+        dag.insert_between(extract.clone(), load.clone(), validate.clone());
+        let names = |dag: &Dag| -> Vec<String> {
+            dag.resolve_execution_order()
+                .unwrap()
+                .into_iter()
+                .map(|node| node.name)
+                .collect()
+        };
+        assert_eq!(names(&dag), vec!["extract", "report", "validate", "load"]);
+        assert_eq!(dag.successors(&extract), [report.clone(), validate.clone()]);
+        assert_eq!(dag.predecessors(&load), [validate]);
+        assert_eq!(dag.indegree[&load], 1);
+        assert_eq!(dag.edges().count(), 3);
+
+        // Without a direct edge the middle node is still wired in
+        let cleanup = node("cleanup");
+        dag.insert_between(report.clone(), load.clone(), cleanup);
+        assert_eq!(dag.indegree[&load], 2);
+        assert_eq!(
+            names(&dag),
+            vec!["extract", "report", "validate", "cleanup", "load"]
+        );
+    }
+
+    #[test]
+    fn test_max_depth_rejects_deeper_chains() {
+        let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+        let (a, b, c, d) = (node("a"), node("b"), node("c"), node("d"));
+        let mut dag = Dag::new("g".into()).with_max_depth(3);
+        assert_eq!(dag.try_add_task_relation(b.clone(), c.clone()), Ok(()));
+        assert_eq!(dag.try_add_task_relation(a.clone(), b.clone()), Ok(()));
+        // A second branch of the same depth is fine
+        assert_eq!(dag.try_add_task_relation(a.clone(), d.clone()), Ok(()));
+
+        let edges = dag.edges().count();
+        let tail = node("tail");
+        assert_eq!(
+            dag.try_add_task_relation(c.clone(), tail.clone()),
+            Err(
+                "edge 'c' -> 'tail' would make the chain a -> b -> c -> tail 4 tasks deep, \
+                 more than the limit of 3"
+                    .to_string()
+            )
+        );
+        // Joining two chains is measured end to end
+        let err = dag.try_add_task_relation(d.clone(), b.clone()).unwrap_err();
+        assert!(err.contains("a -> d -> b -> c 4 tasks deep"), "{}", err);
+        assert_eq!(
+            dag.try_add_task_relation(c, a.clone()),
+            Err("edge 'c' -> 'a' would create a cycle".to_string())
+        );
+        assert_eq!(dag.edges().count(), edges);
+        assert!(!dag.get_all_tasks().contains(&tail));
+
+        // Without a limit only cycles are rejected
+        let mut unlimited = Dag::new("g".into());
+        assert_eq!(
+            unlimited.try_add_task_relation(a.clone(), b.clone()),
+            Ok(())
+        );
+        assert!(unlimited.try_add_task_relation(b, a).is_err());
+        assert_eq!(unlimited.max_depth(), None);
+    }
+
+    #[test]
+    fn test_stabilize_ids_gives_identical_exports() {
+        let build = || {
+            let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+            let (fetch, check, load) = (node("fetch"), node("check"), node("load"));
+            let mut dag = Dag::new("etl".into());
+            dag.add_task_relation(fetch.clone(), check.clone());
+            dag.add_task_relation(fetch.clone(), node("check"));
+            dag.add_task_relation(check, load);
+            dag.add_teardown(node("cleanup"));
+            dag
+        };
+        let (mut first, mut second) = (build(), build());
+        let plan = |dag: &Dag| {
+            dag.plan_with(SortStrategy::InsertionOrder)
+                .unwrap()
+                .to_json()
+        };
+        assert_ne!(plan(&first), plan(&second));
+
+        first.stabilize_ids();
+        second.stabilize_ids();
+        assert_eq!(plan(&first), plan(&second));
+        let ids = |dag: &Dag| -> std::collections::BTreeSet<String> {
+            dag.get_all_tasks()
+                .iter()
+                .chain(dag.teardown_tasks())
+                .map(|node| node.id().to_string())
+                .collect()
+        };
+        assert_eq!(ids(&first), ids(&second));
+        // The two nodes named "check" still get ids of their own
+        assert_eq!(ids(&first).len(), 5);
+
+        assert!(first.structurally_eq(&build()));
+        assert_eq!(first.indegree.values().sum::<usize>(), 3);
+        assert_eq!(first.execute(), Ok(()));
+    }
+
+    #[test]
+    fn test_stabilize_ids_orders_duplicate_names_by_insertion() {
+        let stable_plan = || {
+            let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+            let first = node("x");
+            let mut dag = Dag::new("g".into());
+            dag.add_task_relation(first.clone(), node("x"));
+            dag.add_task_relation(first, node("y"));
+            dag.stabilize_ids();
+            dag.plan_with(SortStrategy::InsertionOrder)
+                .unwrap()
+                .to_json()
+        };
+        let plans: HashSet<String> = (0..20).map(|_| stable_plan()).collect();
+        assert_eq!(plans.len(), 1);
+    }
+
+    #[test]
+    fn test_stabilize_ids_orders_unsequenced_duplicate_names() {
+        let stable_plan = || {
+            let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+            let (first, second) = (node("x"), node("x"));
+            let mut dag = Dag::new("g".into());
+            dag.add_task_relation(first.clone(), second);
+            dag.add_task_relation(first, node("y"));
+            // Copies that lost their sequences, so only the graph can tell them apart
+            let mut dag = dag.map_nodes(|node| {
+                let mut copy = node.clone();
+                copy.sequence = None;
+                copy
+            });
+            dag.stabilize_ids();
+            dag.plan_with(SortStrategy::KahnBfs).unwrap().to_json()
+        };
+        let plans: HashSet<String> = (0..20).map(|_| stable_plan()).collect();
+        assert_eq!(plans.len(), 1);
+    }
+
+    #[test]
+    fn test_ancestors_and_descendants() {
+        let dag = branching_dag();
+        let find = |name: &str| {
+            dag.get_all_tasks()
+                .into_iter()
+                .find(|node| node.name == name)
+                .unwrap()
+        };
+        let mut descendants: Vec<String> = dag
+            .descendants(&find("b1"))
+            .iter()
+            .map(|node| node.name.clone())
+            .collect();
+        descendants.sort();
+        assert_eq!(descendants, vec!["a3", "b2"]);
+
+        let mut ancestors: Vec<String> = dag
+            .ancestors(&find("a3"))
+            .iter()
+            .map(|node| node.name.clone())
+            .collect();
+        ancestors.sort();
+        assert_eq!(ancestors, vec!["a1", "a2", "b1", "b2", "root"]);
+    }
+
+    #[test]
+    fn test_descendants_of_deep_chain() {
+        let mut dag = Dag::new("chain".into());
+        let root = TaskNode::new("n0".to_string(), ok_task);
+        let mut prev = root.clone();
+        for i in 1..50_000 {
+            let next = TaskNode::new(format!("n{}", i), ok_task);
+            dag.add_task_relation(prev, next.clone());
+            prev = next;
+        }
+        assert_eq!(dag.descendants(&root).len(), 49_999);
+        assert_eq!(dag.ancestors(&prev).len(), 49_999);
+    }
+
+    #[test]
+    fn test_add_task_ref_returns_usable_handle() {
+        let mut dag = Dag::new("g".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), ok_task));
+        let b = dag.add_task_ref(TaskNode::new("b".to_string(), ok_task));
+        dag.add_task_relation(a.clone(), b.clone());
+
+        assert_eq!(dag.get_all_tasks().len(), 2);
+        assert_eq!(dag.successors(&a), std::slice::from_ref(&b));
+        assert_eq!(dag.predecessors(&b), &[a]);
+    }
+
+    #[test]
+    fn test_depends_on_follows_paths_upstream() {
+        // a -> b, a -> c, b -> d, c -> d
+        let mut dag = Dag::new("diamond".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), ok_task));
+        let b = dag.add_task_ref(TaskNode::new("b".to_string(), ok_task));
+        let c = dag.add_task_ref(TaskNode::new("c".to_string(), ok_task));
+        let d = dag.add_task_ref(TaskNode::new("d".to_string(), ok_task));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(a.clone(), c.clone());
+        dag.add_task_relation(b.clone(), d.clone());
+        dag.add_task_relation(c.clone(), d.clone());
+
+        assert!(dag.depends_on(&d, &a));
+        assert!(dag.depends_on(&d, &b));
+        assert!(!dag.depends_on(&a, &d));
+        assert!(!dag.depends_on(&b, &c));
+        assert!(!dag.depends_on(&c, &b));
+        assert!(!dag.depends_on(&a, &a));
+    }
+
+    #[test]
+    fn test_reverse_execution_order_of_chain() {
+        let mut dag = Dag::new("chain".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), ok_task));
+        let b = dag.add_task_ref(TaskNode::new("b".to_string(), ok_task));
+        let c = dag.add_task_ref(TaskNode::new("c".to_string(), ok_task));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+
+        let mut forward = dag.resolve_execution_order().unwrap();
+        forward.reverse();
+        assert_eq!(dag.reverse_execution_order().unwrap(), forward);
+        assert_eq!(dag.reverse_execution_order().unwrap(), vec![c, b, a]);
+    }
+
+    #[test]
+    fn test_find_redundant_edges_reports_shortcuts() {
+        // a -> b -> c plus shortcuts a -> c and a -> d, with b -> d via c
+        let mut dag = Dag::new("g".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), ok_task));
+        let b = dag.add_task_ref(TaskNode::new("b".to_string(), ok_task));
+        let c = dag.add_task_ref(TaskNode::new("c".to_string(), ok_task));
+        let d = dag.add_task_ref(TaskNode::new("d".to_string(), ok_task));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+        dag.add_task_relation(c.clone(), d.clone());
+        dag.add_task_relation(a.clone(), c.clone());
+        dag.add_task_relation(a.clone(), d.clone());
+
+        let redundant = dag.find_redundant_edges();
+        assert_eq!(redundant, vec![(a.clone(), c), (a, d)]);
+        assert!(branching_dag().find_redundant_edges().is_empty());
+    }
+
+    #[test]
+    fn test_transitive_reduction_keeps_reachability() {
+        let mut dag = branching_dag();
+        let by_name = |dag: &Dag, name: &str| {
+            dag.get_all_tasks()
+                .into_iter()
+                .find(|node| node.name == name)
+                .unwrap()
+        };
+        // Shortcuts already implied by root -> a1 -> a2 -> a3
+        dag.add_task_relation(by_name(&dag, "root"), by_name(&dag, "a3"));
+        dag.add_task_relation(by_name(&dag, "a1"), by_name(&dag, "a3"));
+
+        let reduced = dag.transitive_reduction();
+        assert_eq!(reduced.edges().count(), dag.edges().count() - 2);
+        assert!(reduced.find_redundant_edges().is_empty());
+        assert!(reduced.verify_consistent().is_ok());
+        for node in dag.get_all_tasks() {
+            let mut before = dag.descendants(&node);
+            let mut after = reduced.descendants(&node);
+            before.sort_by(|a, b| a.name.cmp(&b.name));
+            after.sort_by(|a, b| a.name.cmp(&b.name));
+            assert_eq!(before, after, "reachability from {}", node.name);
+        }
+        assert_valid_order(&dag, &reduced.resolve_execution_order().unwrap());
+    }
+
+    #[test]
+    fn test_seeded_ids_are_reproducible() {
+        let mut ids = SeededIds::new(42);
+        let a = TaskNode::new_seeded("a".to_string(), ok_task, &mut ids);
+        let b = TaskNode::new_seeded("b".to_string(), ok_task, &mut ids);
+        assert_ne!(a.id(), b.id());
+
+        let mut again = SeededIds::new(42);
+        assert_eq!(again.next_id(), a.id());
+        assert_eq!(again.next_id(), b.id());
+        assert_ne!(SeededIds::new(7).next_id(), a.id());
+
+        let parsed = uuid::Uuid::parse_str(a.id()).unwrap();
+        assert_eq!(parsed.get_version_num(), 4);
+    }
+
+    #[test]
+    fn test_execution_order_names_matches_order() {
+        let dag = branching_dag();
+        let mapped: Vec<String> = dag
+            .resolve_execution_order()
+            .unwrap()
+            .iter()
+            .map(|node| node.name.clone())
+            .collect();
+        assert_eq!(dag.execution_order_names().unwrap(), mapped);
+        assert!(Dag::new("empty".into()).execution_order_names().is_err());
+    }
+
+    #[test]
+    fn test_add_relation_auto_creates_missing_nodes() {
+        let mut dag = Dag::new("auto".into());
+        let existing = dag.add_task_ref(TaskNode::new("extract".to_string(), ok_task));
+        let default = || -> Arc<dyn Task> { Arc::new(crate::task::ShellTask::new("true")) };
+        dag.add_relation_auto("extract", "load", default);
+        dag.add_relation_auto("load", "report", default);
+
+        assert_eq!(dag.get_all_tasks().len(), 3);
+        assert!(dag.verify_consistent().is_ok());
+        assert_eq!(
+            dag.execution_order_names().unwrap(),
+            vec!["extract", "load", "report"]
+        );
+        let load = &dag.successors(&existing)[0];
+        assert_eq!(load.name, "load");
+        assert_eq!(load.task_type(), "shell");
+        assert_eq!(existing.task_type(), "closure");
+        assert_eq!(dag.successors(load)[0].name, "report");
+    }
+
+    #[test]
+    fn test_find_all_cycles_reports_each_cycle() {
+        let mut dag = Dag::new("cycles".into());
+        let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+        let (a, b, c, d, e) = (node("a"), node("b"), node("c"), node("d"), node("e"));
+        // a <-> b, then b -> c, and the separate loop c -> d -> e -> c
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), a.clone());
+        dag.add_task_relation(b, c.clone());
+        dag.add_task_relation(c.clone(), d.clone());
+        dag.add_task_relation(d, e.clone());
+        dag.add_task_relation(e, c);
+
+        let names: Vec<Vec<String>> = dag
+            .find_all_cycles()
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|node| node.name).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["a", "b"], vec!["c", "d", "e"]]);
+        assert_eq!(
+            dag.describe_cycles(),
+            "cycle of 2 tasks: a, b\ncycle of 3 tasks: c, d, e\n"
+        );
+        assert!(branching_dag().find_all_cycles().is_empty());
+
+        let mut ring = Dag::new("ring".into());
+        let nodes: Vec<TaskNode> = (0..8).map(|i| node(&format!("n{}", i))).collect();
+        for i in 0..nodes.len() {
+            ring.add_task_relation(nodes[i].clone(), nodes[(i + 1) % nodes.len()].clone());
+        }
+        assert_eq!(
+            ring.describe_cycles(),
+            "cycle of 8 tasks: n0, n1, n2, n3, n4 and 3 more\n"
+        );
+    }
+
+    #[test]
+    fn test_affected_by_includes_downstream_nodes() {
+        // parse(a.csv) -> clean -> report, load(b.csv) -> report, lint(a.csv)
+        let mut dag = Dag::new("files".into());
+        let parse = TaskNode::new("parse".to_string(), ok_task).with_file_inputs(["a.csv"]);
+        let clean = TaskNode::new("clean".to_string(), ok_task);
+        let load = TaskNode::new("load".to_string(), ok_task).with_file_inputs(["b.csv"]);
+        let report = TaskNode::new("report".to_string(), ok_task);
+        let lint = TaskNode::new("lint".to_string(), ok_task).with_file_inputs(["a.csv"]);
+        dag.add_task_relation(parse.clone(), clean.clone());
+        dag.add_task_relation(clean, report.clone());
+        dag.add_task_relation(load, report);
+        dag.add_task(lint);
+
+        let names = |changed: &[&str]| -> Vec<String> {
+            let changed: Vec<PathBuf> = changed.iter().map(PathBuf::from).collect();
+            let mut names: Vec<String> = dag
+                .affected_by(&changed)
+                .into_iter()
+                .map(|node| node.name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&["a.csv"]), vec!["clean", "lint", "parse", "report"]);
+        assert_eq!(names(&["b.csv"]), vec!["load", "report"]);
+        assert!(names(&["c.csv"]).is_empty());
+
+        let order = dag.affected_by(&[PathBuf::from("a.csv")]);
+        let position = |name: &str| order.iter().position(|node| node.name == name);
+        assert!(position("parse") < position("clean"));
+        assert!(position("clean") < position("report"));
+    }
+
+    #[test]
+    fn test_add_relation_by_name_uses_the_default_task() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut dag = Dag::new("proto".into());
+        assert_eq!(
+            dag.add_relation_by_name("a", "b"),
+            Err("no task named 'a' and the DAG has no default task".to_string())
+        );
+        assert!(dag.get_all_tasks().is_empty());
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let mut dag = dag.with_default_task(move || -> Arc<dyn Task> {
+            let counter = counter.clone();
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        });
+        dag.add_relation_by_name("extract", "transform").unwrap();
+        dag.add_relation_by_name("transform", "load").unwrap();
+
+        assert_eq!(
+            dag.execution_order_names().unwrap(),
+            vec!["extract", "transform", "load"]
+        );
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_dag_macro_matches_manual_construction() {
+        let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+        let (a, b, c, d) = (node("a"), node("b"), node("c"), node("d"));
+        let built = crate::dag! { a => b, a => c, b => d, c => d };
+
+        let mut manual = Dag::new("dag".into());
+        manual.add_task_relation(a.clone(), b.clone());
+        manual.add_task_relation(a.clone(), c.clone());
+        manual.add_task_relation(b.clone(), d.clone());
+        manual.add_task_relation(c, d);
+
+        let edges = |dag: &Dag| -> HashSet<(TaskNode, TaskNode)> {
+            dag.edges()
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .collect()
+        };
+        assert_eq!(built.name(), "dag");
+        assert_eq!(edges(&built), edges(&manual));
+        assert_eq!(built.get_all_tasks().len(), manual.get_all_tasks().len());
+
+        let named = crate::dag!("etl"; a => b,);
+        assert_eq!(named.name(), "etl");
+        assert_eq!(named.execution_order_names().unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_connected_components_split_disconnected_subgraphs() {
+        let mut dag = branching_dag();
+        let node = |name: &str| TaskNode::new(name.to_string(), ok_task);
+        let (x, y, z) = (node("x"), node("y"), node("z"));
+        dag.add_task_relation(x, z.clone());
+        dag.add_task_relation(y, z);
+
+        let components = dag.connected_components();
+        assert_eq!(components.len(), 2);
+        let mut names: Vec<Vec<String>> = components
+            .iter()
+            .map(|component| {
+                assert!(component.verify_consistent().is_ok());
+                let mut names: Vec<String> = component
+                    .get_all_tasks()
+                    .into_iter()
+                    .map(|node| node.name)
+                    .collect();
+                names.sort();
+                names
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                vec!["a1", "a2", "a3", "b1", "b2", "root"],
+                vec!["x", "y", "z"]
+            ]
+        );
+        let edges: usize = components.iter().map(|c| c.edges().count()).sum();
+        assert_eq!(edges, dag.edges().count());
+        assert!(components[0].name().starts_with(dag.name()));
+    }
+
+    #[test]
+    fn test_map_tasks_keeps_the_structure() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let real_runs = Arc::new(AtomicUsize::new(0));
+        let mut dag = Dag::new("real".into());
+        let node = |name: &str| {
+            let counter = real_runs.clone();
+            TaskNode::new(name.to_string(), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err("real work".to_string())
+            })
+        };
+        let (a, b, c) = (node("a"), node("b").with_retries(2), node("c"));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(a, c.clone());
+        dag.add_task_relation(b, c);
+        dag.add_teardown(node("cleanup"));
+
+        let mock_runs = Arc::new(AtomicUsize::new(0));
+        let counter = mock_runs.clone();
+        let mocked = dag.map_tasks(|_| -> Arc<dyn Task> {
+            let counter = counter.clone();
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        });
+
+        let edges = |dag: &Dag| -> HashSet<(String, String)> {
+            dag.edges()
+                .map(|(from, to)| (from.id().to_string(), to.id().to_string()))
+                .collect()
+        };
+        assert_eq!(edges(&mocked), edges(&dag));
+        assert!(mocked.verify_consistent().is_ok());
+        assert_eq!(mocked.execution_order_names(), dag.execution_order_names());
+        let b = mocked.get_all_tasks().into_iter().find(|n| n.name == "b");
+        assert_eq!(b.map(|b| b.retries()), Some(2));
+
+        assert_eq!(mocked.execute(), Ok(()));
+        assert_eq!(mock_runs.load(Ordering::SeqCst), 4);
+        assert_eq!(real_runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_approx_size_grows_with_nodes() {
+        let mut dag = Dag::new("size".into());
+        let mut previous = dag.approx_size_bytes();
+        let mut last = dag.add_task_ref(TaskNode::new("n0".to_string(), ok_task));
+        for i in 1..50 {
+            let size = dag.approx_size_bytes();
+            assert!(
+                size > previous,
+                "{} <= {} after {} nodes",
+                size,
+                previous,
+                i
+            );
+            previous = size;
+            let next = TaskNode::new(format!("n{}", i), ok_task);
+            dag.add_task_relation(last, next.clone());
+            last = next;
+        }
+        assert!(dag.approx_size_bytes() > previous);
+    }
+
+    #[test]
+    fn test_max_parallelism_is_widest_level() {
+        // root fans out to four workers, which all feed sink
+        let mut dag = Dag::new("fan".into());
+        let root = TaskNode::new("root".to_string(), ok_task);
+        let sink = TaskNode::new("sink".to_string(), ok_task);
+        for i in 0..4 {
+            let worker = TaskNode::new(format!("w{}", i), ok_task);
+            dag.add_task_relation(root.clone(), worker.clone());
+            dag.add_task_relation(worker, sink.clone());
+        }
+        let widths: Vec<usize> = dag
+            .execution_levels()
+            .unwrap()
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(widths, vec![1, 4, 1]);
+        assert_eq!(dag.max_parallelism().unwrap(), 4);
+
+        let levels: Vec<Vec<String>> = branching_dag()
+            .execution_levels()
+            .unwrap()
+            .into_iter()
+            .map(|level| level.into_iter().map(|node| node.name).collect())
+            .collect();
+        assert_eq!(
+            levels,
+            vec![vec!["root"], vec!["a1", "b1"], vec!["a2", "b2"], vec!["a3"]]
+        );
+        assert_eq!(branching_dag().max_parallelism().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_visit_walks_execution_order() {
+        struct Names(String);
+        impl NodeVisitor for Names {
+            fn visit(&mut self, node: &TaskNode) {
+                self.0.push_str(&node.name);
+            }
+        }
+
+        let dag = branching_dag();
+        let mut names = Names(String::new());
+        dag.visit(&mut names).unwrap();
+        assert_eq!(names.0, dag.execution_order_names().unwrap().concat());
+        assert!(Dag::new("empty".into()).visit(&mut names).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_builder_produces_a_consistent_dag() {
+        let builder = ConcurrentDagBuilder::new("parallel".into());
+        let root = TaskNode::new("root".to_string(), ok_task);
+        builder.add_task(root.clone());
+
+        std::thread::scope(|scope| {
+            for worker in 0..8 {
+                let (builder, root) = (&builder, root.clone());
+                scope.spawn(move || {
+                    let mut previous = root;
+                    for step in 0..25 {
+                        let node = TaskNode::new(format!("w{}-{}", worker, step), ok_task);
+                        builder.add_task_relation(previous, node.clone());
+                        previous = node;
+                    }
+                });
+            }
+        });
+
+        let dag = builder.finish();
+        assert_eq!(dag.verify_consistent(), Ok(()));
+        assert_eq!(dag.get_all_tasks().len(), 1 + 8 * 25);
+        assert_eq!(dag.edges().count(), 8 * 25);
+        assert_eq!(dag.successors(&root).len(), 8);
+        assert_valid_order(&dag, &dag.resolve_execution_order().unwrap());
+    }
 }
@@ -0,0 +1,110 @@
+use std::fmt::Write;
+
+use crate::dag::{Dag, TaskNode};
+
+/// Quotes a DOT identifier or label.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Dag {
+    /// Exports the DAG in Graphviz DOT format
+    ///
+    /// Nodes are identified by id and labelled with their name, so duplicate names
+    /// still render as separate nodes.
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_clusters(|_| None)
+    }
+
+    /// Like `to_dot`, drawing nodes that share a group in a `subgraph cluster_N` block
+    ///
+    /// `group` returns the cluster label for a node, or `None` to leave the node at the
+    /// top level. Clusters appear in the order their first node is executed.
+    pub fn to_dot_with_clusters(&self, group: impl Fn(&TaskNode) -> Option<String>) -> String {
+        let nodes = self.resolve_execution_order().unwrap_or_else(|_| {
+            let mut nodes = self.get_all_tasks();
+            nodes.sort_by(|a, b| a.name.cmp(&b.name));
+            nodes
+        });
+
+        let mut clusters: Vec<(String, Vec<&TaskNode>)> = Vec::new();
+        let mut ungrouped = Vec::new();
+        for node in &nodes {
+            match group(node) {
+                Some(label) => match clusters.iter_mut().find(|(existing, _)| *existing == label) {
+                    Some((_, members)) => members.push(node),
+                    None => clusters.push((label, vec![node])),
+                },
+                None => ungrouped.push(node),
+            }
+        }
+
+        let declare =
+            |node: &TaskNode| format!("{} [label={}];", quote(node.id()), quote(&node.name));
+
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph {} {{", quote(self.name()));
+        for (index, (label, members)) in clusters.iter().enumerate() {
+            let _ = writeln!(out, "    subgraph cluster_{} {{", index);
+            let _ = writeln!(out, "        label={};", quote(label));
+            for node in members {
+                let _ = writeln!(out, "        {}", declare(node));
+            }
+            let _ = writeln!(out, "    }}");
+        }
+        for node in ungrouped {
+            let _ = writeln!(out, "    {}", declare(node));
+        }
+        for node in &nodes {
+            for next in self.successors(node) {
+                let _ = writeln!(out, "    {} -> {};", quote(node.id()), quote(next.id()));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::{PythonTask, ShellTask};
+
+    #[test]
+    fn dot_lists_nodes_and_edges() {
+        let mut dag = Dag::new("etl".into());
+        let a = TaskNode::new("a".to_string(), || Ok(()));
+        let b = TaskNode::new("say \"b\"".to_string(), || Ok(()));
+        dag.add_task_relation(a.clone(), b.clone());
+
+        let dot = dag.to_dot();
+        assert!(dot.starts_with("digraph \"etl\" {\n"));
+        assert!(dot.contains(&format!("\"{}\" [label=\"a\"];", a.id())));
+        assert!(dot.contains(&format!("\"{}\" [label=\"say \\\"b\\\"\"];", b.id())));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", a.id(), b.id())));
+        assert!(!dot.contains("subgraph"));
+    }
+
+    #[test]
+    fn dot_groups_nodes_into_clusters() {
+        let mut dag = Dag::new("etl".into());
+        let fetch = TaskNode::new("fetch".to_string(), ShellTask::new("true"));
+        let clean = TaskNode::new("clean".to_string(), PythonTask::new("pass"));
+        let load = TaskNode::new("load".to_string(), ShellTask::new("true"));
+        let native = TaskNode::new("native".to_string(), || Ok(()));
+        dag.add_task_relation(fetch.clone(), clean.clone());
+        dag.add_task_relation(clean.clone(), load.clone());
+        dag.add_task_relation(load.clone(), native.clone());
+
+        let dot = dag.to_dot_with_clusters(|node| match node.task_type() {
+            "closure" => None,
+            kind => Some(kind.to_string()),
+        });
+
+        assert!(dot.contains("    subgraph cluster_0 {\n        label=\"shell\";\n"));
+        assert!(dot.contains("    subgraph cluster_1 {\n        label=\"python\";\n"));
+        assert_eq!(dot.matches("subgraph").count(), 2);
+        assert!(dot.contains(&format!("\n    \"{}\" [label=\"native\"];", native.id())));
+        assert!(dot.contains(&format!("        \"{}\" [label=\"load\"];", load.id())));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use crate::dag::{Dag, TaskNode};
+use crate::task::{PythonTask, ShellTask};
+
+/// Builds a DAG from the line-based text DSL
+///
+/// Each non-empty line is either a task definition or a chain of edges:
+///
+/// ```text
+/// # comments start with '#'
+/// fetch: shell "curl -O https://example.com/data.csv"
+/// clean: python "import csv"
+/// fetch -> clean
+/// ```
+///
+/// Task kinds are `shell` and `python`; their argument is a double-quoted string in which
+/// `\"` and `\\` are escapes. Edges may be chained (`a -> b -> c`) and may only refer to
+/// tasks defined on an earlier line. Errors name the offending line number.
+pub fn from_dsl(src: &str) -> Result<Dag, String> {
+    let mut dag = Dag::new("dsl".to_string());
+    let mut nodes: HashMap<String, TaskNode> = HashMap::new();
+
+    for (index, raw) in src.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // A definition's colon comes before anything in its quoted argument
+        let is_edge = match (line.find("->"), line.find(':')) {
+            (Some(arrow), Some(colon)) => arrow < colon,
+            (arrow, _) => arrow.is_some(),
+        };
+
+        if is_edge {
+            let names: Vec<&str> = line.split("->").map(str::trim).collect();
+            let mut chain = Vec::with_capacity(names.len());
+            for name in names {
+                if name.is_empty() {
+                    return Err(format!("line {}: missing task name in edge", line_no));
+                }
+                let node = nodes
+                    .get(name)
+                    .ok_or_else(|| format!("line {}: unknown task '{}'", line_no, name))?;
+                chain.push(node.clone());
+            }
+            for pair in chain.windows(2) {
+                dag.add_task_relation(pair[0].clone(), pair[1].clone());
+            }
+            continue;
+        }
+
+        let (name, definition) = line
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected a task definition or an edge", line_no))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("line {}: invalid task name '{}'", line_no, name));
+        }
+        if nodes.contains_key(name) {
+            return Err(format!(
+                "line {}: task '{}' is already defined",
+                line_no, name
+            ));
+        }
+
+        let definition = definition.trim();
+        let (kind, argument) = definition.split_once(char::is_whitespace).ok_or_else(|| {
+            format!(
+                "line {}: expected a task kind and a quoted argument",
+                line_no
+            )
+        })?;
+        let argument =
+            parse_quoted(argument.trim()).map_err(|err| format!("line {}: {}", line_no, err))?;
+
+        let node = match kind {
+            "shell" => TaskNode::new(name.to_string(), ShellTask::new(argument)),
+            "python" => TaskNode::new(name.to_string(), PythonTask::new(argument)),
+            other => return Err(format!("line {}: unknown task kind '{}'", line_no, other)),
+        };
+        dag.add_task(node.clone());
+        nodes.insert(name.to_string(), node);
+    }
+
+    Ok(dag)
+}
+
+/// Parses a double-quoted string that must make up the whole of `text`.
+fn parse_quoted(text: &str) -> Result<String, String> {
+    let mut chars = text.chars();
+    if chars.next() != Some('"') {
+        return Err("expected a double-quoted argument".into());
+    }
+
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                return if chars.as_str().trim().is_empty() {
+                    Ok(value)
+                } else {
+                    Err("unexpected text after closing quote".into())
+                };
+            }
+            '\\' => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => value.push(escaped),
+                Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+                None => break,
+            },
+            other => value.push(other),
+        }
+    }
+
+    Err("unterminated string".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn successor_names(dag: &Dag, name: &str) -> Vec<String> {
+        let node = dag
+            .get_all_tasks()
+            .into_iter()
+            .find(|node| node.name == name)
+            .unwrap();
+        dag.successors(&node)
+            .iter()
+            .map(|node| node.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parses_tasks_and_edges() {
+        let src = r#"
+            # a tiny pipeline
+            a: shell "echo \"hi\" -> out.txt"
+            b: python "print('b')"
+            c: shell "true"
+            d: shell "true"
+
+            a -> b -> d
+            a -> c
+        "#;
+        let dag = from_dsl(src).unwrap();
+
+        assert_eq!(dag.get_all_tasks().len(), 4);
+        assert_eq!(successor_names(&dag, "a"), vec!["b", "c"]);
+        assert_eq!(successor_names(&dag, "b"), vec!["d"]);
+        assert!(successor_names(&dag, "d").is_empty());
+
+        let b = dag
+            .get_all_tasks()
+            .into_iter()
+            .find(|node| node.name == "b")
+            .unwrap();
+        assert_eq!(b.task_type(), "python");
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let err = from_dsl("a: shell \"true\"\n\na -> missing").unwrap_err();
+        assert_eq!(err, "line 3: unknown task 'missing'");
+
+        let err = from_dsl("a: ruby \"puts 1\"").unwrap_err();
+        assert_eq!(err, "line 1: unknown task kind 'ruby'");
+
+        let err = from_dsl("# ok\na: shell \"unterminated").unwrap_err();
+        assert_eq!(err, "line 2: unterminated string");
+    }
+}
@@ -0,0 +1,1565 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::control::{CancelToken, PauseGate, RetryLimiter};
+use crate::dag::{Dag, JoinPolicy, SortStrategy, TaskNode};
+use crate::hash::{HashCache, inputs_hash};
+use crate::observer::{DagObserver, JsonLinesObserver, NoopObserver, ProgressEvent};
+use crate::queue::RunQueue;
+use crate::report::{ExecutionReport, NodeReport, NodeStatus};
+use crate::task::{RunEnv, TaskOutcome, TaskRun};
+
+/// What happens to the remaining teardown tasks once one of them fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TeardownPolicy {
+    /// Run every teardown task and report each failure.
+    #[default]
+    Gather,
+    /// Skip the teardown tasks after the first failing one.
+    FailFast,
+}
+
+/// Live scheduling state of a parallel run, for diagnosing a run that stops making progress.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulingState {
+    /// Upstream nodes each not-yet-started node is still waiting on, in execution order.
+    pub remaining: Vec<(String, usize)>,
+    /// Nodes whose upstream nodes are done, waiting for budget or the pause gate.
+    pub ready: Vec<String>,
+    /// Nodes whose tasks are running.
+    pub in_flight: Vec<String>,
+}
+
+impl fmt::Display for SchedulingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |names: &[String]| match names {
+            [] => "(none)".to_string(),
+            names => names.join(", "),
+        };
+        writeln!(f, "in flight: {}", list(&self.in_flight))?;
+        writeln!(f, "ready: {}", list(&self.ready))?;
+        for (name, left) in self.remaining.iter().filter(|(_, left)| *left > 0) {
+            writeln!(f, "waiting: {} ({} upstream left)", name, left)?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings for one run.
+pub(crate) struct RunSettings<'a> {
+    pub(crate) observer: &'a dyn DagObserver,
+    /// Checked before each main node starts.
+    pub(crate) gate: Option<&'a PauseGate>,
+    /// Timeout for nodes that do not set their own.
+    pub(crate) default_timeout: Option<Duration>,
+    /// Names of the nodes whose tasks are running right now.
+    pub(crate) running: Option<&'a Mutex<HashSet<String>>>,
+    /// Names of the nodes that have finished running, successfully or not.
+    pub(crate) finished: Option<&'a Mutex<HashSet<String>>>,
+    pub(crate) teardown_policy: TeardownPolicy,
+    /// Updated by the parallel engine whenever it waits for a task to finish.
+    pub(crate) state: Option<&'a Mutex<SchedulingState>>,
+    /// Consulted before each node-level retry.
+    pub(crate) retry_limiter: Option<&'a RetryLimiter>,
+    /// Results of fingerprinted tasks, shared by nodes doing the same work.
+    pub(crate) dedup: Option<&'a DedupCache>,
+    /// Directory receiving a `{node name}.log` file per subprocess task; see `log_file_name`.
+    pub(crate) log_dir: Option<&'a Path>,
+    /// Once cancelled, nodes fail instead of starting another attempt.
+    pub(crate) cancel: Option<&'a CancelToken>,
+    /// Input hashes from earlier runs; nodes whose inputs match are skipped.
+    pub(crate) hash_cache: Option<&'a HashCache>,
+    /// Saved as nodes start and finish; nodes it lists as done are skipped.
+    pub(crate) queue: Option<&'a RunQueue>,
+}
+
+/// One slot per task fingerprint; the first node to reach a slot runs the task.
+pub(crate) type DedupCache = Mutex<HashMap<String, Arc<OnceLock<TaskRun>>>>;
+
+impl<'a> RunSettings<'a> {
+    pub(crate) fn new(observer: &'a dyn DagObserver) -> Self {
+        RunSettings {
+            observer,
+            gate: None,
+            default_timeout: None,
+            running: None,
+            finished: None,
+            teardown_policy: TeardownPolicy::Gather,
+            state: None,
+            retry_limiter: None,
+            dedup: None,
+            log_dir: None,
+            cancel: None,
+            hash_cache: None,
+            queue: None,
+        }
+    }
+
+    /// `run_node`, keeping `running`, `finished` and the run queue up to date around it.
+    fn run_tracked(&self, dag: &Dag, node: &TaskNode) -> TaskRun {
+        let lock = |running: &'a Mutex<HashSet<String>>| {
+            running
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        };
+        if let Some(running) = self.running {
+            lock(running).insert(node.name.clone());
+        }
+        let run = self.run_queued(dag, node);
+        if let Some(finished) = self.finished {
+            lock(finished).insert(node.name.clone());
+        }
+        if let Some(running) = self.running {
+            lock(running).remove(&node.name);
+        }
+        run
+    }
+
+    /// `run_node`, skipping nodes the run queue lists as done and saving the queue
+    /// before and after the others.
+    fn run_queued(&self, dag: &Dag, node: &TaskNode) -> TaskRun {
+        let Some(queue) = self.queue else {
+            return run_node(dag, node, self);
+        };
+        let failed = |err| TaskRun {
+            result: Err(err),
+            exit_code: None,
+        };
+        if queue.is_done(node) {
+            return TaskRun {
+                result: Ok(TaskOutcome::default()),
+                exit_code: None,
+            };
+        }
+        if let Err(err) = queue.start(node) {
+            return failed(err);
+        }
+        let run = run_node(dag, node, self);
+        match queue.finish(node, run.result.is_ok()) {
+            Err(err) if run.result.is_ok() => failed(err),
+            _ => run,
+        }
+    }
+}
+
+/// Runs a node's task, retrying it up to the node's retry limit, then its fallback.
+///
+/// Disabled and up-to-date nodes succeed without running their task, as do nodes whose
+/// input hashes match the run's hash cache. Otherwise the node's delay is waited out
+/// first, and each attempt is bounded by the node's timeout, or by the run's default
+/// timeout when the node has none.
+fn run_node(dag: &Dag, node: &TaskNode, settings: &RunSettings<'_>) -> TaskRun {
+    let skipped = || TaskRun {
+        result: Ok(TaskOutcome::default()),
+        exit_code: None,
+    };
+    if !dag.is_enabled(node) || node.is_up_to_date() {
+        return skipped();
+    }
+    let Some((cache, hash)) = settings
+        .hash_cache
+        .and_then(|cache| Some((cache, inputs_hash(node)?)))
+    else {
+        return run_fresh(node, settings);
+    };
+    if cache.matches(node, &hash) {
+        return skipped();
+    }
+    let mut run = run_fresh(node, settings);
+    if run.result.is_ok()
+        && let Err(err) = cache.record(node, hash)
+    {
+        run.result = Err(err);
+    }
+    run
+}
+
+/// `run_node` for a node that has to run, sharing the result with identical nodes
+/// when deduplicating.
+fn run_fresh(node: &TaskNode, settings: &RunSettings<'_>) -> TaskRun {
+    let run = || run_attempts(node, settings);
+    let Some((cache, fingerprint)) = settings.dedup.zip(node.fingerprint()) else {
+        return run();
+    };
+    // Retry and timeout settings change the outcome, so they are part of the key
+    let key = format!("{:?}:{}", (node.retries(), node.timeout()), fingerprint);
+    let slot = cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(key)
+        .or_default()
+        .clone();
+    slot.get_or_init(run).clone()
+}
+
+/// Log file name for the node called `name`, which stays inside the log directory
+///
+/// Every character other than an ASCII letter, digit, `-`, `_` or `.` becomes `_`, so
+/// separators and `..` components cannot leave the directory.
+fn log_file_name(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.log", safe)
+}
+
+/// Runs `node`'s attempts and then its fallback, with the exit code of whichever task
+/// produced the result.
+fn run_attempts(node: &TaskNode, settings: &RunSettings<'_>) -> TaskRun {
+    if !node.delay().is_zero() {
+        thread::sleep(node.delay());
+    }
+    let timeout = node.timeout().or(settings.default_timeout);
+    let log = settings
+        .log_dir
+        .map(|dir| dir.join(log_file_name(&node.name)));
+    let env = RunEnv {
+        log: log.as_deref(),
+        cancel: settings.cancel,
+        deadline: None,
+    };
+    let mut retry = 0;
+    loop {
+        if settings.cancel.is_some_and(CancelToken::is_cancelled) {
+            return TaskRun {
+                result: Err("run cancelled".into()),
+                exit_code: None,
+            };
+        }
+        let run = node.run_with_status(timeout, &env);
+        let Err(err) = &run.result else {
+            return run;
+        };
+        if retry < node.retries() {
+            if let Some(limiter) = settings.retry_limiter {
+                limiter.acquire();
+            }
+            retry += 1;
+            settings
+                .observer
+                .on_event(&ProgressEvent::Retrying(node.name.clone(), retry));
+            continue;
+        }
+        let Some(fallback) = node.fallback_node() else {
+            return run;
+        };
+        let fallback_run = fallback.run_with_status(timeout, &env);
+        return TaskRun {
+            result: fallback_run
+                .result
+                .map_err(|fallback_err| format!("{}; fallback failed: {}", err, fallback_err)),
+            exit_code: fallback_run.exit_code,
+        };
+    }
+}
+
+/// Skip reason for nodes cut off by a `BranchTask`.
+const BRANCH_NOT_TAKEN: &str = "branch not taken";
+
+/// Index of every node in `order`.
+fn positions(order: &[TaskNode]) -> HashMap<&TaskNode, usize> {
+    order
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node, index))
+        .collect()
+}
+
+/// Report with every node of `order` still pending.
+fn pending_report(order: &[TaskNode]) -> ExecutionReport {
+    ExecutionReport {
+        nodes: order
+            .iter()
+            .map(|node| NodeReport {
+                name: node.name.clone(),
+                status: NodeStatus::Pending,
+                duration: Duration::ZERO,
+                warnings: Vec::new(),
+                exit_code: None,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Overall run error for a failed node, naming the node.
+fn node_failure(node: &TaskNode, err: &str) -> String {
+    format!("Task '{}' failed: {}", node.name, err)
+}
+
+impl Dag {
+    /// Sequential execution with progress notifications
+    ///
+    /// Runs nodes one at a time in topological order, reporting each start, retry and
+    /// outcome to `observer`. Execution stops at the first failure: every node that has
+    /// not run yet is reported as skipped, naming the failed or skipped upstream node when
+    /// there is one. Teardown tasks run last, whether or not the main run succeeded.
+    pub fn execute_with_observer(&self, observer: &dyn DagObserver) -> Result<(), String> {
+        self.run_sequential(&RunSettings::new(observer)).1
+    }
+
+    /// Sequential execution that logs every event to `writer` as JSON Lines
+    ///
+    /// See `JsonLinesObserver` for the line format.
+    pub fn execute_to_writer<W: Write + Send>(&self, writer: &mut W) -> Result<(), String> {
+        self.execute_with_observer(&JsonLinesObserver::new(writer))
+    }
+
+    /// Sequential execution that always returns a report
+    ///
+    /// Behaves like `execute`, but alongside the overall result returns an
+    /// `ExecutionReport` recording what happened to every node, so the tasks that
+    /// completed before a failure are still visible. Teardown outcomes are recorded
+    /// separately and never affect the returned result.
+    pub fn try_execute(&self) -> (ExecutionReport, Result<(), String>) {
+        self.run_sequential(&RunSettings::new(&NoopObserver))
+    }
+
+    /// Like `try_execute`, running each distinct task fingerprint only once
+    ///
+    /// Nodes whose tasks share a fingerprint (see `Task::fingerprint`) and have the same
+    /// retry and timeout settings run the task for the first such node only; the others
+    /// are given its result. Tasks without a fingerprint always run.
+    pub fn try_execute_deduplicated(&self) -> (ExecutionReport, Result<(), String>) {
+        let cache = DedupCache::default();
+        self.run_sequential(&RunSettings {
+            dedup: Some(&cache),
+            ..RunSettings::new(&NoopObserver)
+        })
+    }
+
+    pub(crate) fn run_sequential(
+        &self,
+        settings: &RunSettings<'_>,
+    ) -> (ExecutionReport, Result<(), String>) {
+        let (mut report, result) = self.run_main_sequential(settings);
+        report.teardown = self.run_teardown(settings);
+        (report, result)
+    }
+
+    fn run_main_sequential(
+        &self,
+        settings: &RunSettings<'_>,
+    ) -> (ExecutionReport, Result<(), String>) {
+        match self.resolve_execution_order() {
+            Ok(order) => self.run_main_in_order(settings, &order),
+            Err(err) => (ExecutionReport::default(), Err(err)),
+        }
+    }
+
+    /// Runs `order`, which must be a valid topological order, then the teardown tasks.
+    pub(crate) fn run_sequential_in_order(
+        &self,
+        settings: &RunSettings<'_>,
+        order: &[TaskNode],
+    ) -> (ExecutionReport, Result<(), String>) {
+        let (mut report, result) = self.run_main_in_order(settings, order);
+        report.teardown = self.run_teardown(settings);
+        (report, result)
+    }
+
+    fn run_main_in_order(
+        &self,
+        settings: &RunSettings<'_>,
+        order: &[TaskNode],
+    ) -> (ExecutionReport, Result<(), String>) {
+        let observer = settings.observer;
+        let position = positions(order);
+        let mut report = pending_report(order);
+        let mut failed: Option<(&TaskNode, String)> = None;
+        let mut branches: HashMap<&TaskNode, String> = HashMap::new();
+
+        for (index, node) in order.iter().enumerate() {
+            let cut_off = !self.predecessors(node).is_empty()
+                && !self.has_live_input(node, &report, &position, &branches);
+            if failed.is_some() || cut_off {
+                let reason = match failed {
+                    Some(_) => self.skip_reason(node, &report, &position),
+                    None => BRANCH_NOT_TAKEN.to_string(),
+                };
+                observer.on_event(&ProgressEvent::Skipped(node.name.clone(), reason.clone()));
+                report.nodes[index].status = NodeStatus::Skipped(reason);
+                continue;
+            }
+
+            if let Some(gate) = settings.gate {
+                gate.wait_until_open();
+            }
+            observer.on_event(&ProgressEvent::Started(node.name.clone()));
+            let started = Instant::now();
+            let run = settings.run_tracked(self, node);
+            let result = run
+                .result
+                .and_then(|outcome| self.check_branch(node, outcome));
+            let entry = &mut report.nodes[index];
+            entry.duration = started.elapsed();
+            entry.exit_code = run.exit_code;
+            match result {
+                Ok(outcome) => {
+                    observer.on_event(&ProgressEvent::Finished(node.name.clone()));
+                    entry.status = NodeStatus::Success;
+                    entry.warnings = outcome.warnings;
+                    if let Some(branch) = outcome.branch {
+                        branches.insert(node, branch);
+                    }
+                }
+                Err(err) => {
+                    observer.on_event(&ProgressEvent::Failed(node.name.clone(), err.clone()));
+                    entry.status = NodeStatus::Failed(err.clone());
+                    failed = Some((node, err));
+                }
+            }
+        }
+
+        let result = match failed {
+            Some((node, err)) => Err(node_failure(node, &err)),
+            None => Ok(()),
+        };
+        (report, result)
+    }
+
+    /// Whether at least one upstream node succeeded and, if it is a branch, chose `node`.
+    fn has_live_input(
+        &self,
+        node: &TaskNode,
+        report: &ExecutionReport,
+        position: &HashMap<&TaskNode, usize>,
+        branches: &HashMap<&TaskNode, String>,
+    ) -> bool {
+        self.predecessors(node).iter().any(|upstream| {
+            report.nodes[position[upstream]].status == NodeStatus::Success
+                && branches
+                    .get(upstream)
+                    .is_none_or(|branch| *branch == node.name)
+        })
+    }
+
+    /// Fails a branch outcome that names anything but a direct successor of `node`.
+    fn check_branch(&self, node: &TaskNode, outcome: TaskOutcome) -> Result<TaskOutcome, String> {
+        match &outcome.branch {
+            Some(branch)
+                if !self
+                    .successors(node)
+                    .iter()
+                    .any(|next| next.name == *branch) =>
+            {
+                Err(format!("branch '{}' is not a downstream task", branch))
+            }
+            _ => Ok(outcome),
+        }
+    }
+
+    /// Why `node` is skipped after a failure, naming a failed or skipped upstream node.
+    fn skip_reason(
+        &self,
+        node: &TaskNode,
+        report: &ExecutionReport,
+        position: &HashMap<&TaskNode, usize>,
+    ) -> String {
+        self.predecessors(node)
+            .iter()
+            .find_map(|upstream| match &report.nodes[position[upstream]].status {
+                NodeStatus::Failed(_) => Some(format!("upstream task '{}' failed", upstream.name)),
+                NodeStatus::Skipped(_) => {
+                    Some(format!("upstream task '{}' was skipped", upstream.name))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| "execution aborted".to_string())
+    }
+
+    /// Runs the teardown tasks in reverse registration order
+    ///
+    /// Under `TeardownPolicy::FailFast` the tasks after the first failure are skipped;
+    /// otherwise every task runs whatever the others' outcome.
+    fn run_teardown(&self, settings: &RunSettings<'_>) -> Vec<NodeReport> {
+        let observer = settings.observer;
+        let mut failed = false;
+        self.teardown_tasks()
+            .iter()
+            .rev()
+            .map(|node| {
+                let mut entry = NodeReport {
+                    name: node.name.clone(),
+                    status: NodeStatus::Pending,
+                    duration: Duration::ZERO,
+                    warnings: Vec::new(),
+                    exit_code: None,
+                };
+                if failed && settings.teardown_policy == TeardownPolicy::FailFast {
+                    let reason = "earlier teardown task failed".to_string();
+                    observer.on_event(&ProgressEvent::Skipped(node.name.clone(), reason.clone()));
+                    entry.status = NodeStatus::Skipped(reason);
+                    return entry;
+                }
+
+                observer.on_event(&ProgressEvent::Started(node.name.clone()));
+                let started = Instant::now();
+                let run = run_node(self, node, settings);
+                entry.duration = started.elapsed();
+                entry.exit_code = run.exit_code;
+                match run.result {
+                    Ok(outcome) => {
+                        observer.on_event(&ProgressEvent::Finished(node.name.clone()));
+                        entry.status = NodeStatus::Success;
+                        entry.warnings = outcome.warnings;
+                    }
+                    Err(err) => {
+                        observer.on_event(&ProgressEvent::Failed(node.name.clone(), err.clone()));
+                        entry.status = NodeStatus::Failed(err);
+                        failed = true;
+                    }
+                }
+                entry
+            })
+            .collect()
+    }
+
+    /// Parallel execution under a total cost budget
+    ///
+    /// Ready tasks are dispatched greedily, highest `priority` first and otherwise in
+    /// topological order, as long as the summed `cost` of all in-flight tasks stays
+    /// within `max_total_cost`. A ready task that does not fit is passed over in favour
+    /// of cheaper ready tasks that do.
+    ///
+    /// A task whose own cost exceeds the budget could never be dispatched, so it is
+    /// rejected before anything runs. After the first failure no new tasks are started;
+    /// tasks already in flight are allowed to finish and the first error is returned.
+    /// Teardown tasks run afterwards either way.
+    pub fn execute_with_cost_budget(&self, max_total_cost: usize) -> Result<(), String> {
+        let settings = RunSettings::new(&NoopObserver);
+        let (_, result) = self.run_with_cost_budget(
+            &settings,
+            max_total_cost,
+            TaskNode::cost,
+            SortStrategy::KahnBfs,
+        );
+        self.run_teardown(&settings);
+        result
+    }
+
+    /// Parallel execution with at most `max_concurrency` tasks in flight
+    ///
+    /// Equivalent to a cost budget of `max_concurrency` where every task costs 1, so
+    /// when more tasks are ready than slots are free, higher priorities start first.
+    /// Each task runs on its own thread named after the node, so backtraces and
+    /// profilers show which task a thread belongs to.
+    pub fn execute_parallel(&self, max_concurrency: usize) -> Result<(), String> {
+        self.try_execute_parallel(max_concurrency).1
+    }
+
+    /// Parallel execution that always returns a report, like `try_execute`.
+    pub fn try_execute_parallel(
+        &self,
+        max_concurrency: usize,
+    ) -> (ExecutionReport, Result<(), String>) {
+        self.try_execute_parallel_with(max_concurrency, SortStrategy::KahnBfs)
+    }
+
+    /// Like `try_execute_parallel`, dispatching ready tasks in the order `strategy` sorts them
+    ///
+    /// With `SortStrategy::CriticalPathFirst`, tasks on the longest estimated path start
+    /// before tasks that have slack, which shortens the overall run when slots are scarce.
+    pub fn try_execute_parallel_with(
+        &self,
+        max_concurrency: usize,
+        strategy: SortStrategy,
+    ) -> (ExecutionReport, Result<(), String>) {
+        if max_concurrency == 0 {
+            return (
+                ExecutionReport::default(),
+                Err("max_concurrency must be at least 1".into()),
+            );
+        }
+        self.run_parallel(&RunSettings::new(&NoopObserver), max_concurrency, strategy)
+    }
+
+    /// Unit-cost parallel run followed by teardown; `max_concurrency` must be at least 1.
+    pub(crate) fn run_parallel(
+        &self,
+        settings: &RunSettings<'_>,
+        max_concurrency: usize,
+        strategy: SortStrategy,
+    ) -> (ExecutionReport, Result<(), String>) {
+        let (mut report, result) =
+            self.run_with_cost_budget(settings, max_concurrency, |_| 1, strategy);
+        report.teardown = self.run_teardown(settings);
+        (report, result)
+    }
+
+    fn run_with_cost_budget(
+        &self,
+        settings: &RunSettings<'_>,
+        max_total_cost: usize,
+        cost_of: impl Fn(&TaskNode) -> usize,
+        strategy: SortStrategy,
+    ) -> (ExecutionReport, Result<(), String>) {
+        let order = match self.resolve_execution_order_with(strategy) {
+            Ok(order) => order,
+            Err(err) => return (ExecutionReport::default(), Err(err)),
+        };
+
+        if let Some(node) = order.iter().find(|node| cost_of(node) > max_total_cost) {
+            let err = format!(
+                "Task '{}' cost {} exceeds budget {}",
+                node.name,
+                cost_of(node),
+                max_total_cost
+            );
+            return (ExecutionReport::default(), Err(err));
+        }
+
+        // Topological position, used to keep the ready list in a stable order
+        let position = positions(&order);
+        // Ready nodes are dispatched in this order, highest priority first
+        let rank = |node: &TaskNode| (Reverse(node.priority()), position[node]);
+        let mut report = pending_report(&order);
+
+        let mut remaining: HashMap<&TaskNode, usize> = order
+            .iter()
+            .map(|node| (node, self.predecessors(node).len()))
+            .collect();
+
+        let mut ready: Vec<&TaskNode> = order
+            .iter()
+            .filter(|node| self.predecessors(node).is_empty())
+            .collect();
+        ready.sort_by_key(|node| rank(node));
+
+        let mut in_flight: Vec<&TaskNode> = Vec::new();
+        let mut in_flight_cost = 0;
+        let mut first_error: Option<(&TaskNode, String)> = None;
+        let mut branches: HashMap<&TaskNode, String> = HashMap::new();
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+
+            loop {
+                if first_error.is_none() {
+                    if let Some(gate) = settings.gate
+                        && !ready.is_empty()
+                    {
+                        gate.wait_until_open();
+                    }
+                    let mut index = 0;
+                    while index < ready.len() {
+                        let node = ready[index];
+                        let resource_busy = node.resource().is_some_and(|resource| {
+                            in_flight
+                                .iter()
+                                .any(|running| running.resource() == Some(resource))
+                        });
+                        if resource_busy || in_flight_cost + cost_of(node) > max_total_cost {
+                            index += 1;
+                            continue;
+                        }
+
+                        ready.remove(index);
+                        in_flight.push(node);
+                        in_flight_cost += cost_of(node);
+
+                        let tx = tx.clone();
+                        thread::Builder::new()
+                            .name(node.name.clone())
+                            .spawn_scoped(scope, move || {
+                                let started = Instant::now();
+                                let run = settings.run_tracked(self, node);
+                                let result = run
+                                    .result
+                                    .and_then(|outcome| self.check_branch(node, outcome));
+                                // The receiver outlives every worker inside the scope
+                                let _ = tx.send((node, result, run.exit_code, started.elapsed()));
+                            })
+                            .expect("failed to spawn task thread");
+                    }
+                }
+
+                if in_flight.is_empty() {
+                    break;
+                }
+
+                if let Some(state) = settings.state {
+                    let names =
+                        |nodes: &[&TaskNode]| nodes.iter().map(|n| n.name.clone()).collect();
+                    let queued: HashSet<&TaskNode> =
+                        ready.iter().chain(&in_flight).copied().collect();
+                    let waiting = order.iter().filter(|node| {
+                        report.nodes[position[node]].status == NodeStatus::Pending
+                            && !queued.contains(node)
+                    });
+                    *state
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) = SchedulingState {
+                        remaining: waiting
+                            .map(|node| (node.name.clone(), remaining[node]))
+                            .collect(),
+                        ready: names(&ready),
+                        in_flight: names(&in_flight),
+                    };
+                }
+
+                let (node, result, exit_code, duration) =
+                    rx.recv().expect("worker channel closed unexpectedly");
+                in_flight.retain(|running| *running != node);
+                in_flight_cost -= cost_of(node);
+                let entry = &mut report.nodes[position[node]];
+                entry.duration = duration;
+                entry.exit_code = exit_code;
+
+                match result {
+                    Ok(outcome) => {
+                        entry.status = NodeStatus::Success;
+                        entry.warnings = outcome.warnings;
+                        if let Some(branch) = outcome.branch {
+                            branches.insert(node, branch);
+                        }
+
+                        // Nodes cut off by a branch settle without running, which may in
+                        // turn settle their own successors
+                        let mut settled = vec![node];
+                        while let Some(done) = settled.pop() {
+                            for neighbor in self.successors(done) {
+                                let Some(d) = remaining.get_mut(neighbor) else {
+                                    continue;
+                                };
+                                if *d == 0 {
+                                    continue;
+                                }
+                                let live = report.nodes[position[done]].status
+                                    == NodeStatus::Success
+                                    && branches
+                                        .get(done)
+                                        .is_none_or(|branch| *branch == neighbor.name);
+                                // An `Any` node fires on its first live upstream and then
+                                // ignores the rest
+                                *d = match neighbor.join_policy() {
+                                    JoinPolicy::Any if live => 0,
+                                    _ => *d - 1,
+                                };
+                                if *d > 0 {
+                                    continue;
+                                }
+                                if self.has_live_input(neighbor, &report, &position, &branches) {
+                                    let at = ready.partition_point(|n| rank(n) < rank(neighbor));
+                                    ready.insert(at, neighbor);
+                                } else {
+                                    report.nodes[position[neighbor]].status =
+                                        NodeStatus::Skipped(BRANCH_NOT_TAKEN.to_string());
+                                    settled.push(neighbor);
+                                }
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        entry.status = NodeStatus::Failed(err.clone());
+                        first_error.get_or_insert((node, err));
+                    }
+                }
+            }
+        });
+
+        // Anything still pending was never started because of the failure
+        for (index, node) in order.iter().enumerate() {
+            if report.nodes[index].status == NodeStatus::Pending {
+                let reason = self.skip_reason(node, &report, &position);
+                report.nodes[index].status = NodeStatus::Skipped(reason);
+            }
+        }
+
+        let result = match first_error {
+            Some((node, err)) => Err(node_failure(node, &err)),
+            None => Ok(()),
+        };
+        (report, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::{BranchTask, FnOutcomeTask, RetryTask, Task};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn tracked_node(
+        name: &str,
+        cost: usize,
+        in_flight: &Arc<AtomicUsize>,
+        peak: &Arc<AtomicUsize>,
+        runs: &Arc<AtomicUsize>,
+    ) -> TaskNode {
+        let in_flight = in_flight.clone();
+        let peak = peak.clone();
+        let runs = runs.clone();
+        let task = move || {
+            let now = in_flight.fetch_add(cost, Ordering::SeqCst) + cost;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            in_flight.fetch_sub(cost, Ordering::SeqCst);
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+        TaskNode::new(name.to_string(), task).with_cost(cost)
+    }
+
+    #[test]
+    fn test_cost_budget_never_exceeded() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let mut dag = Dag::new("g".into());
+        let root = tracked_node("root", 1, &in_flight, &peak, &runs);
+        for (i, cost) in [3, 2, 2, 1, 1, 4].into_iter().enumerate() {
+            let node = tracked_node(&format!("n{}", i), cost, &in_flight, &peak, &runs);
+            dag.add_task_relation(root.clone(), node);
+        }
+
+        assert_eq!(dag.execute_with_cost_budget(4), Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 7);
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+        // More than one task must have overlapped for the budget to matter
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_shared_resource_serializes_independent_tasks() {
+        let (db_in_flight, db_peak) =
+            (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let mut dag = Dag::new("g".into());
+        for i in 0..3 {
+            let name = format!("migrate{}", i);
+            let node = tracked_node(&name, 1, &db_in_flight, &db_peak, &runs);
+            dag.add_task(node.with_resource("db"));
+            dag.add_task(tracked_node(
+                &format!("fetch{}", i),
+                1,
+                &in_flight,
+                &peak,
+                &runs,
+            ));
+        }
+
+        assert_eq!(dag.execute_parallel(6), Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 6);
+        assert_eq!(db_peak.load(Ordering::SeqCst), 1);
+        // Tasks without the resource still ran side by side
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_cost_budget_rejects_oversized_task() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("big".to_string(), || Ok(())).with_cost(5));
+        let err = dag.execute_with_cost_budget(4).unwrap_err();
+        assert!(err.contains("big"));
+    }
+
+    #[test]
+    fn test_cost_budget_stops_after_failure() {
+        let mut dag = Dag::new("g".into());
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counter = ran.clone();
+        let a = TaskNode::new("a".to_string(), || Err("boom".to_string()));
+        let b = TaskNode::new("b".to_string(), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        dag.add_task_relation(a, b);
+        assert_eq!(
+            dag.execute_with_cost_budget(1),
+            Err("Task 'a' failed: boom".to_string())
+        );
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_observer_reports_retry_and_skip() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let flaky = TaskNode::new("flaky".to_string(), move || {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err("transient".to_string())
+            } else {
+                Ok(())
+            }
+        })
+        .with_retries(2);
+        let broken = TaskNode::new("broken".to_string(), || Err("boom".to_string()));
+        let downstream = TaskNode::new("downstream".to_string(), || Ok(()));
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(flaky.clone(), broken.clone());
+        dag.add_task_relation(broken.clone(), downstream.clone());
+
+        let events = Mutex::new(Vec::new());
+        let observer = |event: &ProgressEvent| events.lock().unwrap().push(event.clone());
+        assert_eq!(
+            dag.execute_with_observer(&observer),
+            Err("Task 'broken' failed: boom".to_string())
+        );
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ProgressEvent::Started("flaky".into()),
+                ProgressEvent::Retrying("flaky".into(), 1),
+                ProgressEvent::Finished("flaky".into()),
+                ProgressEvent::Started("broken".into()),
+                ProgressEvent::Failed("broken".into(), "boom".into()),
+                ProgressEvent::Skipped("downstream".into(), "upstream task 'broken' failed".into()),
+            ]
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_try_execute_reports_partial_progress() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), || Ok(()));
+        let b = TaskNode::new("b".to_string(), || Err("boom".to_string()));
+        let c = TaskNode::new("c".to_string(), || Ok(()));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+
+        let (report, result) = dag.try_execute();
+        assert_eq!(result, Err("Task 'b' failed: boom".to_string()));
+        assert_eq!(report.nodes.len(), 3);
+        assert_eq!(report.status("a"), Some(&NodeStatus::Success));
+        assert_eq!(report.status("b"), Some(&NodeStatus::Failed("boom".into())));
+        assert!(matches!(report.status("c"), Some(NodeStatus::Skipped(_))));
+    }
+
+    #[test]
+    fn test_teardown_runs_after_failure() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name: &'static str, result: Result<(), String>| {
+            let log = log.clone();
+            TaskNode::new(name.to_string(), move || {
+                log.lock().unwrap().push(name);
+                result.clone()
+            })
+        };
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task(recorder("main", Err("boom".into())));
+        dag.add_teardown(recorder("release_lock", Ok(())));
+        dag.add_teardown(recorder("remove_tmp", Err("busy".into())));
+
+        let (report, result) = dag.try_execute();
+        assert_eq!(result, Err("Task 'main' failed: boom".to_string()));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["main", "remove_tmp", "release_lock"]
+        );
+        assert_eq!(report.teardown.len(), 2);
+        assert_eq!(report.teardown[0].status, NodeStatus::Failed("busy".into()));
+        assert_eq!(report.teardown[1].status, NodeStatus::Success);
+    }
+
+    #[test]
+    fn test_parallel_threads_are_named_after_nodes() {
+        let mut dag = Dag::new("g".into());
+        for name in ["extract", "transform"] {
+            dag.add_task(TaskNode::new(
+                name.to_string(),
+                move || match thread::current().name() {
+                    Some(current) if current == name => Ok(()),
+                    other => Err(format!("unexpected thread name {:?}", other)),
+                },
+            ));
+        }
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        assert!(dag.execute_parallel(0).is_err());
+    }
+
+    #[test]
+    fn test_parallel_error_names_failing_node() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("fine".to_string(), || Ok(())));
+        dag.add_task(TaskNode::new("flaky_upload".to_string(), || {
+            Err("connection reset".to_string())
+        }));
+        let err = dag.execute_parallel(2).unwrap_err();
+        assert_eq!(err, "Task 'flaky_upload' failed: connection reset");
+    }
+
+    #[test]
+    fn test_disabled_node_is_skipped_but_satisfies_dependents() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name: &'static str| {
+            let log = log.clone();
+            TaskNode::new(name.to_string(), move || {
+                log.lock().unwrap().push(name);
+                Ok(())
+            })
+        };
+        let a = recorder("a");
+        let b = recorder("b");
+        let c = recorder("c");
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+        dag.set_enabled(&b, false);
+        assert_eq!(dag.verify_consistent(), Ok(()));
+
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        assert_eq!(*log.lock().unwrap(), vec!["a", "c", "a", "c"]);
+
+        dag.set_enabled(&b, true);
+        log.lock().unwrap().clear();
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(*log.lock().unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_disabled_node_stays_disabled_through_older_handles() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name: &'static str| {
+            let log = log.clone();
+            TaskNode::new(name.to_string(), move || {
+                log.lock().unwrap().push(name);
+                Ok(())
+            })
+        };
+        let (a, b, c) = (recorder("a"), recorder("b"), recorder("c"));
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.set_enabled(&b, false);
+        // `b` was cloned before it was disabled and is wired up again afterwards
+        dag.add_task_relation(b.clone(), c);
+        assert!(!dag.is_enabled(&b));
+
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        assert_eq!(*log.lock().unwrap(), vec!["a", "c", "a", "c"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_report_records_subprocess_exit_codes() {
+        use crate::task::ShellTask;
+
+        let mut dag = Dag::new("g".into());
+        let ok = TaskNode::new("ok".to_string(), ShellTask::new("true"));
+        let closure = TaskNode::new("closure".to_string(), || Ok(()));
+        let fails = TaskNode::new("fails".to_string(), ShellTask::new("exit 3"));
+        dag.add_task_relation(ok.clone(), closure.clone());
+        dag.add_task_relation(closure, fails);
+
+        for (report, _) in [dag.try_execute(), dag.try_execute_parallel(2)] {
+            assert_eq!(report.get("ok").unwrap().exit_code, Some(0));
+            assert_eq!(report.get("closure").unwrap().exit_code, None);
+            assert_eq!(report.get("fails").unwrap().exit_code, Some(3));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_node_timeout_kills_the_subprocess() {
+        use crate::task::ShellTask;
+
+        let marker =
+            std::env::temp_dir().join(format!("seadog-timeout-{}.marker", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let mut dag = Dag::new("g".into());
+        dag.add_task(
+            TaskNode::new(
+                "slow".to_string(),
+                ShellTask::new(format!("sleep 1; touch '{}'", marker.display())),
+            )
+            .with_timeout(Duration::from_millis(100))
+            .with_retries(1),
+        );
+
+        let started = Instant::now();
+        assert!(dag.execute().is_err());
+        assert!(started.elapsed() < Duration::from_millis(900));
+        // Neither attempt's shell is left to create the marker
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_report_records_the_fallback_exit_code() {
+        use crate::task::ShellTask;
+
+        let mut dag = Dag::new("g".into());
+        dag.add_task(
+            TaskNode::new("flaky".to_string(), ShellTask::new("exit 3"))
+                .with_fallback(Arc::new(ShellTask::new("true"))),
+        );
+
+        for (report, result) in [dag.try_execute(), dag.try_execute_parallel(2)] {
+            assert_eq!(result, Ok(()));
+            assert_eq!(report.get("flaky").unwrap().exit_code, Some(0));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_deduplicated_run_executes_identical_commands_once() {
+        use crate::task::ShellTask;
+
+        let log = std::env::temp_dir().join(format!("seadog-dedup-{}.log", std::process::id()));
+        let command = format!("echo ran >> '{}'", log.display());
+        let mut dag = Dag::new("g".into());
+        let first = TaskNode::new("first".to_string(), ShellTask::new(command.clone()));
+        let second = TaskNode::new("second".to_string(), ShellTask::new(command.clone()));
+        let other = TaskNode::new("other".to_string(), ShellTask::new("true"));
+        dag.add_task_relation(first.clone(), second.clone());
+        dag.add_task_relation(second, other);
+        assert_eq!(first.fingerprint(), dag.successors(&first)[0].fingerprint());
+
+        let (report, result) = dag.try_execute_deduplicated();
+        assert_eq!(result, Ok(()));
+        assert!(
+            report
+                .nodes
+                .iter()
+                .all(|node| node.status == NodeStatus::Success)
+        );
+        assert_eq!(report.get("second").unwrap().exit_code, Some(0));
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "ran\n");
+
+        // Without deduplication both nodes run the command
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 3);
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_fallback_runs_when_the_primary_fails() {
+        use crate::task::Task;
+
+        let mut dag = Dag::new("g".into());
+        let fallback = || -> Arc<dyn Task> { Arc::new(|| Ok(())) };
+        let rescued = TaskNode::new("rescued".to_string(), || Err("primary down".to_string()))
+            .with_retries(1)
+            .with_fallback(fallback());
+        let doomed = TaskNode::new("doomed".to_string(), || Err("primary down".to_string()))
+            .with_fallback(Arc::new(|| Err("fallback down".to_string())));
+        dag.add_task_relation(rescued, doomed);
+
+        let (report, result) = dag.try_execute();
+        assert_eq!(report.status("rescued"), Some(&NodeStatus::Success));
+        let err = "primary down; fallback failed: fallback down".to_string();
+        assert_eq!(
+            report.status("doomed"),
+            Some(&NodeStatus::Failed(err.clone()))
+        );
+        assert!(result.unwrap_err().contains(&err));
+    }
+
+    #[test]
+    fn test_parallel_dispatch_starts_high_priority_tasks_first() {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let mut dag = Dag::new("g".into());
+        let root = dag.add_task_ref(TaskNode::new("root".to_string(), || Ok(())));
+        for (name, priority) in [("low", -1), ("mid", 0), ("high", 5), ("urgent", 9)] {
+            let log = started.clone();
+            let node = TaskNode::new(name.to_string(), move || {
+                log.lock().unwrap().push(name);
+                thread::sleep(Duration::from_millis(20));
+                Ok(())
+            })
+            .with_priority(priority);
+            dag.add_task_relation(root.clone(), node);
+        }
+
+        assert_eq!(dag.execute_parallel(1), Ok(()));
+        assert_eq!(
+            *started.lock().unwrap(),
+            vec!["urgent", "high", "mid", "low"]
+        );
+
+        // With two slots the two highest priorities take them
+        started.lock().unwrap().clear();
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        let mut order = started.lock().unwrap().clone();
+        order[..2].sort();
+        order[2..].sort();
+        assert_eq!(order, vec!["high", "urgent", "low", "mid"]);
+    }
+
+    #[test]
+    fn test_group_round_robin_alternates_between_groups() {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let mut dag = Dag::new("g".into());
+        for (group, count) in [("greedy", 4), ("small", 2)] {
+            for i in 0..count {
+                let log = started.clone();
+                let node = TaskNode::new(format!("{}-{}", group, i), move || {
+                    log.lock().unwrap().push(group);
+                    Ok(())
+                });
+                dag.add_task(node.with_group(group));
+            }
+        }
+
+        let (_, result) = dag.try_execute_parallel_with(1, SortStrategy::GroupRoundRobin);
+        assert_eq!(result, Ok(()));
+        let groups = started.lock().unwrap().clone();
+        assert_eq!(groups.len(), 6);
+        assert_ne!(groups[0], groups[1]);
+        assert_eq!((groups[0], groups[1]), (groups[2], groups[3]));
+        assert_eq!(groups[4..], ["greedy", "greedy"]);
+    }
+
+    #[test]
+    fn test_delayed_node_starts_after_its_delay() {
+        let finished = Arc::new(Mutex::new(None));
+        let started = Arc::new(Mutex::new(None));
+        let (done, begun) = (finished.clone(), started.clone());
+        let first = TaskNode::new("first".to_string(), move || {
+            *done.lock().unwrap() = Some(Instant::now());
+            Ok(())
+        });
+        let delay = Duration::from_millis(100);
+        let second = TaskNode::new("second".to_string(), move || {
+            *begun.lock().unwrap() = Some(Instant::now());
+            Ok(())
+        })
+        .with_delay(delay);
+        let mut dag = Dag::new("g".into());
+        dag.add_task_relation(first, second);
+
+        let gap = || {
+            let finished = finished.lock().unwrap().take().unwrap();
+            started.lock().unwrap().take().unwrap() - finished
+        };
+        assert_eq!(dag.execute(), Ok(()));
+        assert!(gap() >= delay);
+        assert_eq!(dag.execute_parallel(2), Ok(()));
+        assert!(gap() >= delay);
+    }
+
+    #[test]
+    fn test_up_to_date_outputs_skip_the_task() {
+        use std::fs::{self, File};
+        use std::time::SystemTime;
+
+        let dir = std::env::temp_dir().join(format!("seadog-make-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.txt"), dir.join("out.txt"));
+        let touch = |path: &std::path::Path, secs: u64| {
+            let file = File::create(path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let build = TaskNode::new("build".to_string(), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .with_file_inputs([&input])
+        .with_file_outputs([&output]);
+        let mut dag = Dag::new("g".into());
+        dag.add_task(build.clone());
+
+        // No output yet
+        touch(&input, 1_000);
+        assert!(!build.is_up_to_date());
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Fresh output
+        touch(&output, 2_000);
+        assert!(build.is_up_to_date());
+        let (report, result) = dag.try_execute();
+        assert_eq!(result, Ok(()));
+        assert_eq!(report.status("build"), Some(&NodeStatus::Success));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Input changed after the output was built
+        touch(&input, 3_000);
+        assert!(!build.is_up_to_date());
+        assert_eq!(dag.execute_parallel(1), Ok(()));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_any_join_starts_after_first_upstream() {
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let upstream = |name: &'static str, millis: u64| {
+            let finished = finished.clone();
+            TaskNode::new(name.to_string(), move || {
+                thread::sleep(Duration::from_millis(millis));
+                finished.lock().unwrap().push(name);
+                Ok(())
+            })
+        };
+        let slow1 = upstream("slow1", 300);
+        let slow2 = upstream("slow2", 300);
+        let fast = upstream("fast", 0);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let joined = {
+            let finished = finished.clone();
+            let seen = seen.clone();
+            TaskNode::new("join".to_string(), move || {
+                *seen.lock().unwrap() = finished.lock().unwrap().clone();
+                Ok(())
+            })
+            .with_join_policy(JoinPolicy::Any)
+        };
+
+        let mut dag = Dag::new("g".into());
+        for up in [&slow1, &fast, &slow2] {
+            dag.add_task_relation(up.clone(), joined.clone());
+        }
+
+        assert_eq!(dag.execute_parallel(4), Ok(()));
+        // The join ran once, after only the fast upstream had finished
+        assert_eq!(*seen.lock().unwrap(), vec!["fast"]);
+        assert_eq!(finished.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_try_execute_parallel_reports_outcomes() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), || Ok(()));
+        let b = TaskNode::new("b".to_string(), || Err("boom".to_string()));
+        let c = TaskNode::new("c".to_string(), || Ok(()));
+        let d = TaskNode::new("d".to_string(), || Ok(()));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+        dag.add_task_relation(c.clone(), d.clone());
+
+        let (report, result) = dag.try_execute_parallel(2);
+        assert_eq!(result, Err("Task 'b' failed: boom".to_string()));
+        assert_eq!(report.status("a"), Some(&NodeStatus::Success));
+        assert_eq!(report.status("b"), Some(&NodeStatus::Failed("boom".into())));
+        assert_eq!(
+            report.status("c"),
+            Some(&NodeStatus::Skipped("upstream task 'b' failed".into()))
+        );
+        assert_eq!(
+            report.status("d"),
+            Some(&NodeStatus::Skipped("upstream task 'c' was skipped".into()))
+        );
+    }
+
+    #[test]
+    fn test_warnings_are_reported_without_failing() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new(
+            "a".to_string(),
+            FnOutcomeTask::new(|| Ok(TaskOutcome::warning("3 rows dropped"))),
+        );
+        let b = TaskNode::new("b".to_string(), || Ok(()));
+        dag.add_task_relation(a.clone(), b.clone());
+
+        let (report, result) = dag.try_execute();
+        assert_eq!(result, Ok(()));
+        assert_eq!(report.status("a"), Some(&NodeStatus::Success));
+        assert_eq!(report.get("a").unwrap().warnings, vec!["3 rows dropped"]);
+        assert_eq!(report.warnings(), vec![("a", "3 rows dropped")]);
+
+        let (report, _) = dag.try_execute_parallel(2);
+        assert_eq!(report.warnings(), vec![("a", "3 rows dropped")]);
+    }
+
+    #[test]
+    fn test_critical_path_first_starts_the_long_chain_first() {
+        // Three 100ms roots with two slots; only `head` has a 300ms successor, so it
+        // must start in the first batch for the run to finish in 400ms rather than 500ms
+        let (started_tx, started_rx) = mpsc::channel();
+        // Tasks block on this until the test has seen which ones started first
+        let gate = Arc::new(std::sync::RwLock::new(()));
+        let node = |name: &'static str, ms| {
+            let (started_tx, gate) = (started_tx.clone(), gate.clone());
+            TaskNode::new(name.to_string(), move || {
+                started_tx.send(name).unwrap();
+                drop(gate.read().unwrap());
+                Ok(())
+            })
+            .with_estimated_duration(Duration::from_millis(ms))
+        };
+        let mut dag = Dag::new("g".into());
+        dag.add_task(node("side_a", 100));
+        dag.add_task(node("side_b", 100));
+        dag.add_task_relation(node("head", 100), node("tail", 300));
+
+        let order = dag
+            .resolve_execution_order_with(SortStrategy::CriticalPathFirst)
+            .unwrap();
+        assert_eq!(order[0].name, "head");
+
+        let hold = gate.write().unwrap();
+        thread::scope(|scope| {
+            let run =
+                scope.spawn(|| dag.try_execute_parallel_with(2, SortStrategy::CriticalPathFirst));
+            let first_batch = [started_rx.recv().unwrap(), started_rx.recv().unwrap()];
+            let third_started = started_rx.try_recv().is_ok();
+            drop(hold);
+            assert_eq!(run.join().unwrap().1, Ok(()));
+            assert!(first_batch.contains(&"head"), "{:?}", first_batch);
+            assert!(!third_started);
+        });
+        let rest: Vec<&str> = started_rx.try_iter().collect();
+        assert_eq!(rest.len(), 2);
+        assert!(rest.contains(&"tail"));
+    }
+
+    #[test]
+    fn test_execute_to_writer_emits_json_lines() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), || Ok(())).with_retries(1);
+        let b = TaskNode::new("b".to_string(), || Err("bad \"input\"".to_string()));
+        let c = TaskNode::new("c".to_string(), || Ok(()));
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b.clone(), c.clone());
+
+        let mut out = Vec::new();
+        assert!(dag.execute_to_writer(&mut out).is_err());
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let events: Vec<(&str, &str)> = lines
+            .iter()
+            .map(|line| {
+                (
+                    line["event"].as_str().unwrap(),
+                    line["node"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("start", "a"),
+                ("finish", "a"),
+                ("start", "b"),
+                ("fail", "b"),
+                ("skip", "c")
+            ]
+        );
+        assert_eq!(lines[3]["error"], "bad \"input\"");
+        assert_eq!(lines[4]["reason"], "upstream task 'b' failed");
+        assert!(lines.iter().all(|line| line["ts"].as_u64().unwrap() > 0));
+    }
+
+    #[test]
+    fn test_branch_runs_only_the_chosen_subtree() {
+        // pick -> left -> left_child -> join, pick -> right -> right_child -> join
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let node = |name: &str| {
+            let ran = ran.clone();
+            let owned = name.to_string();
+            TaskNode::new(name.to_string(), move || {
+                ran.lock().unwrap().push(owned.clone());
+                Ok(())
+            })
+        };
+        let mut dag = Dag::new("g".into());
+        let pick = TaskNode::new(
+            "pick".to_string(),
+            BranchTask::new(|| Ok("right".to_string())),
+        );
+        let (left, left_child) = (node("left"), node("left_child"));
+        let (right, right_child) = (node("right"), node("right_child"));
+        let join = node("join").with_join_policy(JoinPolicy::Any);
+        dag.add_task_relation(pick.clone(), left.clone());
+        dag.add_task_relation(pick.clone(), right.clone());
+        dag.add_task_relation(left.clone(), left_child.clone());
+        dag.add_task_relation(right.clone(), right_child.clone());
+        dag.add_task_relation(left_child.clone(), join.clone());
+        dag.add_task_relation(right_child.clone(), join.clone());
+
+        let check = |report: &ExecutionReport| {
+            for name in ["pick", "right", "right_child", "join"] {
+                assert_eq!(report.status(name), Some(&NodeStatus::Success), "{}", name);
+            }
+            for name in ["left", "left_child"] {
+                assert_eq!(
+                    report.status(name),
+                    Some(&NodeStatus::Skipped("branch not taken".into())),
+                    "{}",
+                    name
+                );
+            }
+            let mut ran = ran.lock().unwrap();
+            ran.sort();
+            assert_eq!(*ran, vec!["join", "right", "right_child"]);
+            ran.clear();
+        };
+
+        let (report, result) = dag.try_execute();
+        assert_eq!(result, Ok(()));
+        check(&report);
+
+        let (report, result) = dag.try_execute_parallel(4);
+        assert_eq!(result, Ok(()));
+        check(&report);
+    }
+
+    #[test]
+    fn test_branch_to_unknown_node_fails() {
+        let mut dag = Dag::new("g".into());
+        let pick = TaskNode::new(
+            "pick".to_string(),
+            BranchTask::new(|| Ok("nope".to_string())),
+        );
+        dag.add_task_relation(pick, TaskNode::new("next".to_string(), || Ok(())));
+        assert_eq!(
+            dag.execute(),
+            Err("Task 'pick' failed: branch 'nope' is not a downstream task".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tasks_receive_their_node_id() {
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl Task for Recorder {
+            fn execute(&self) -> Result<(), String> {
+                Err("expected a node id".into())
+            }
+            fn execute_with_id(&self, node_id: &str) -> Result<(), String> {
+                self.0.lock().unwrap().push(node_id.to_string());
+                Ok(())
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut dag = Dag::new("g".into());
+        let plain = TaskNode::new("plain".to_string(), Recorder(seen.clone()));
+        let wrapped = TaskNode::new(
+            "wrapped".to_string(),
+            RetryTask::new(Recorder(seen.clone()), 1),
+        )
+        .with_timeout(Duration::from_secs(5));
+        dag.add_task_relation(plain.clone(), wrapped.clone());
+
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![plain.id().to_string(), wrapped.id().to_string()]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use crate::dag::{Dag, TaskNode};
+use crate::report::{ExecutionReport, NodeStatus};
+
+type Rollback = Box<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+/// Set of nodes that succeed or fail together
+///
+/// A group does not change how its members are scheduled. After a run it is failed if
+/// any member failed, and the rollback, if there is one, is then run to compensate for
+/// the members that did succeed.
+pub struct TaskGroup {
+    name: String,
+    members: Vec<TaskNode>,
+    rollback: Option<Rollback>,
+}
+
+impl TaskGroup {
+    pub fn new(name: String) -> Self {
+        TaskGroup {
+            name,
+            members: Vec::new(),
+            rollback: None,
+        }
+    }
+
+    pub fn with_member(mut self, node: TaskNode) -> Self {
+        self.members.push(node);
+        self
+    }
+
+    /// Sets the compensating action run when some member of the group fails.
+    pub fn with_rollback<F>(mut self, rollback: F) -> Self
+    where
+        F: Fn() -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.rollback = Some(Box::new(rollback));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn members(&self) -> &[TaskNode] {
+        &self.members
+    }
+
+    /// Checks the members' outcomes in `report`, rolling back if any of them failed.
+    ///
+    /// Members missing from the report or skipped do not fail the group on their own.
+    pub fn settle(&self, report: &ExecutionReport) -> Result<(), String> {
+        let failed = self
+            .members
+            .iter()
+            .find(|member| matches!(report.status(&member.name), Some(NodeStatus::Failed(_))));
+        let Some(failed) = failed else {
+            return Ok(());
+        };
+
+        let mut err = format!(
+            "Group '{}' failed: task '{}' failed",
+            self.name, failed.name
+        );
+        if let Some(rollback) = &self.rollback
+            && let Err(rollback_err) = rollback()
+        {
+            err.push_str(&format!("; rollback failed: {}", rollback_err));
+        }
+        Err(err)
+    }
+}
+
+impl fmt::Debug for TaskGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskGroup")
+            .field("name", &self.name)
+            .field("members", &self.members)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Dag {
+    /// Like `try_execute`, then settles each of `groups` in order
+    ///
+    /// The result is the run's own error if there is one, otherwise the first group
+    /// failure. Every group is settled either way, so each failed group rolls back.
+    pub fn try_execute_with_groups(
+        &self,
+        groups: &[TaskGroup],
+    ) -> (ExecutionReport, Result<(), String>) {
+        let (report, mut result) = self.try_execute();
+        for group in groups {
+            let settled = group.settle(&report);
+            if result.is_ok() {
+                result = settled;
+            }
+        }
+        (report, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn rolls_back_when_a_member_fails() {
+        let mut dag = Dag::new("g".into());
+        let stage = TaskNode::new("stage".to_string(), || Ok(()));
+        let commit = TaskNode::new("commit".to_string(), || Err("disk full".to_string()));
+        let audit = TaskNode::new("audit".to_string(), || Ok(()));
+        dag.add_task_relation(stage.clone(), commit.clone());
+        dag.add_task(audit.clone());
+
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+        let counter = rollbacks.clone();
+        let group = TaskGroup::new("write".into())
+            .with_member(stage)
+            .with_member(commit)
+            .with_rollback(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+        let untouched = TaskGroup::new("audit".into())
+            .with_member(audit)
+            .with_rollback(|| panic!("must not roll back"));
+
+        let (report, result) = dag.try_execute_with_groups(&[group, untouched]);
+        assert!(result.unwrap_err().contains("Task 'commit' failed"));
+        assert_eq!(report.status("stage"), Some(&NodeStatus::Success));
+        assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn succeeding_group_does_not_roll_back() {
+        let mut dag = Dag::new("g".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), || Ok(())));
+        let group = TaskGroup::new("only".into())
+            .with_member(a)
+            .with_rollback(|| panic!("must not roll back"));
+
+        let (_, result) = dag.try_execute_with_groups(&[group]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn rollback_errors_are_reported() {
+        let mut dag = Dag::new("g".into());
+        let a = dag.add_task_ref(TaskNode::new("a".to_string(), || Err("boom".to_string())));
+        let group = TaskGroup::new("only".into())
+            .with_member(a)
+            .with_rollback(|| Err("no snapshot".into()));
+
+        let report = dag.try_execute().0;
+        assert_eq!(
+            group.settle(&report).unwrap_err(),
+            "Group 'only' failed: task 'a' failed; rollback failed: no snapshot"
+        );
+    }
+}
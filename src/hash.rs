@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::dag::{Dag, TaskNode};
+use crate::executor::RunSettings;
+use crate::observer::NoopObserver;
+use crate::report::ExecutionReport;
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Incremental SHA-256, as specified in FIPS 180-4.
+pub(crate) struct Sha256 {
+    state: [u32; 8],
+    pending: Vec<u8>,
+    length: u64,
+}
+
+impl Sha256 {
+    pub(crate) fn new() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            pending: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if !self.pending.is_empty() {
+            let take = data.len().min(64 - self.pending.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            let block: [u8; 64] = self.pending[..].try_into().expect("a full block");
+            self.compress(&block);
+            self.pending.clear();
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().expect("chunks are 64 bytes"));
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    /// The digest as lowercase hex.
+    pub(crate) fn finish_hex(self) -> String {
+        self.finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.resize((119 - self.pending.len()) % 64 + 1, 0);
+        padding.extend_from_slice(&bits.to_be_bytes());
+        self.update(&padding);
+        debug_assert!(self.pending.is_empty());
+        let mut digest = [0u8; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().expect("chunks are 4 bytes"));
+        }
+        for i in 16..64 {
+            let w15 = schedule[i - 15];
+            let w2 = schedule[i - 2];
+            let s0 = w15.rotate_right(7) ^ w15.rotate_right(18) ^ (w15 >> 3);
+            let s1 = w2.rotate_right(17) ^ w2.rotate_right(19) ^ (w2 >> 10);
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (&constant, &word) in ROUND_CONSTANTS.iter().zip(&schedule) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(constant)
+                .wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e) = (g, f, e, d.wrapping_add(t1));
+            (d, c, b, a) = (c, b, a, t1.wrapping_add(t2));
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// HMAC-SHA256 of `message` under `key`, as specified in RFC 2104, as lowercase hex.
+pub(crate) fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        block[..32].copy_from_slice(&hasher.finish());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let padded = |pad: u8| block.map(|byte| byte ^ pad);
+    let mut inner = Sha256::new();
+    inner.update(&padded(0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(&padded(0x5c));
+    outer.update(&inner.finish());
+    outer.finish_hex()
+}
+
+/// SHA-256 over every input file of `node`, in order, with each path and length
+///
+/// `None` when the node has no inputs or one of them cannot be read.
+pub(crate) fn inputs_hash(node: &TaskNode) -> Option<String> {
+    if node.file_inputs().is_empty() {
+        return None;
+    }
+    let mut hasher = Sha256::new();
+    for path in node.file_inputs() {
+        let contents = fs::read(path).ok()?;
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&(contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Some(hasher.finish_hex())
+}
+
+/// Content hashes of node inputs from earlier runs, saved in a file
+///
+/// The file holds a JSON object mapping node names to hashes, so node names should
+/// be unique. A node whose `file_inputs` hash to the stored value is
+/// skipped; see `Dag::try_execute_with_hash_cache`.
+#[derive(Debug)]
+pub struct HashCache {
+    path: PathBuf,
+    hashes: Mutex<BTreeMap<String, String>>,
+}
+
+impl HashCache {
+    /// Loads the cache at `path`, starting empty when the file does not exist yet.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, String> {
+        let path = path.into();
+        let failed = |e: io::Error| format!("could not read hash cache {}: {}", path.display(), e);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(failed(e)),
+        };
+        let hashes = if text.is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_json::from_str(&text)
+                .map_err(|e| format!("invalid hash cache {}: {}", path.display(), e))?
+        };
+        Ok(HashCache {
+            path,
+            hashes: Mutex::new(hashes),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hash stored for the node called `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.lock().get(name).cloned()
+    }
+
+    /// Whether `hash` is the one stored for `node`.
+    pub(crate) fn matches(&self, node: &TaskNode, hash: &str) -> bool {
+        self.lock()
+            .get(&node.name)
+            .is_some_and(|stored| stored == hash)
+    }
+
+    /// Stores `hash` for `node` and saves the whole cache
+    ///
+    /// Like the run queue, the cache is written to a sibling temp file and renamed
+    /// over the old one, so a crash mid-write leaves the previous file intact.
+    pub(crate) fn record(&self, node: &TaskNode, hash: String) -> Result<(), String> {
+        let mut hashes = self.lock();
+        hashes.insert(node.name.clone(), hash);
+        let text = serde_json::to_string_pretty(&*hashes).expect("hash caches always serialize");
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, text)
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("could not save hash cache {}: {}", self.path.display(), e))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.hashes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Dag {
+    /// Like `try_execute`, skipping nodes whose input files have not changed
+    ///
+    /// A node with `file_inputs` is treated like a disabled node when the SHA-256 of
+    /// their contents matches the hash `cache` stored after the node last succeeded.
+    /// Nodes without inputs, or with an input that cannot be read, always run.
+    pub fn try_execute_with_hash_cache(
+        &self,
+        cache: &HashCache,
+    ) -> (ExecutionReport, Result<(), String>) {
+        self.run_sequential(&RunSettings {
+            hash_cache: Some(cache),
+            ..RunSettings::new(&NoopObserver)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.finish_hex()
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            sha256(long),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+
+        // Feeding the input in pieces gives the same digest
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let mut hasher = Sha256::new();
+        for piece in data.chunks(37) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish_hex(), sha256(&data));
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231_vectors() {
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first
+        assert_eq!(
+            hmac_sha256_hex(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn unchanged_inputs_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("seadog-hash-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.txt");
+        fs::write(&input, "first").unwrap();
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let mut dag = Dag::new("hashed".into());
+        dag.add_task(
+            TaskNode::new("build".to_string(), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .with_file_inputs([&input]),
+        );
+        let run = || {
+            let cache = HashCache::open(dir.join("hashes")).unwrap();
+            assert_eq!(dag.try_execute_with_hash_cache(&cache).1, Ok(()));
+            runs.load(Ordering::SeqCst)
+        };
+
+        assert_eq!(run(), 1);
+        // Identical content is skipped, even after rewriting the file
+        fs::write(&input, "first").unwrap();
+        assert_eq!(run(), 1);
+        fs::write(&input, "second").unwrap();
+        assert_eq!(run(), 2);
+        assert_eq!(run(), 2);
+
+        let cache = HashCache::open(dir.join("hashes")).unwrap();
+        assert_eq!(cache.get("build").map(|hash| hash.len()), Some(64));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_with_tabs_and_newlines_survive_a_reload() {
+        let dir = std::env::temp_dir().join(format!("seadog-hash-names-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hashes");
+
+        let cache = HashCache::open(&path).unwrap();
+        let node = TaskNode::new("build\tall\nnow".to_string(), || Ok(()));
+        cache.record(&node, "abc".to_string()).unwrap();
+        assert!(!dir.join("hashes.tmp").exists());
+
+        let cache = HashCache::open(&path).unwrap();
+        assert_eq!(cache.get("build\tall\nnow").as_deref(), Some("abc"));
+        assert_eq!(cache.get("build"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
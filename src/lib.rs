@@ -0,0 +1,19 @@
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod clock;
+pub mod context;
+pub mod control;
+pub mod dag;
+pub mod dot;
+pub mod dsl;
+pub mod executor;
+pub mod group;
+pub mod hash;
+pub mod lint;
+pub mod observer;
+pub mod plan;
+mod queue;
+pub mod report;
+pub mod scheduler;
+pub mod task;
+pub mod yaml;
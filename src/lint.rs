@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use crate::dag::Dag;
+
+/// Non-fatal advisory about a graph that is valid but probably not what was intended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// A node with no edges at all, in a graph that has other nodes.
+    OrphanNode(String),
+    /// Every node has an upstream dependency, so nothing can start.
+    NoRoot,
+    /// Every node has a downstream dependent, so nothing finishes the pipeline.
+    NoLeaf,
+    /// More than one node carries this name.
+    DuplicateName(String),
+}
+
+impl Dag {
+    /// Advisory checks on the graph's shape
+    ///
+    /// Returns warnings sorted by kind and name. None of them stop the DAG from being
+    /// executed; a graph with no root or no leaf will however fail to sort because it
+    /// contains a cycle.
+    pub fn lint(&self) -> Vec<Lint> {
+        let nodes = self.get_all_tasks();
+        let mut lints = Vec::new();
+        if nodes.is_empty() {
+            return lints;
+        }
+
+        let mut orphans: Vec<String> = Vec::new();
+        let mut by_name: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut has_root = false;
+        let mut has_leaf = false;
+
+        for node in &nodes {
+            let upstream = self.predecessors(node);
+            let downstream = self.successors(node);
+            has_root |= upstream.is_empty();
+            has_leaf |= downstream.is_empty();
+            if nodes.len() > 1 && upstream.is_empty() && downstream.is_empty() {
+                orphans.push(node.name.clone());
+            }
+            *by_name.entry(&node.name).or_default() += 1;
+        }
+
+        orphans.sort();
+        lints.extend(orphans.into_iter().map(Lint::OrphanNode));
+        if !has_root {
+            lints.push(Lint::NoRoot);
+        }
+        if !has_leaf {
+            lints.push(Lint::NoLeaf);
+        }
+        lints.extend(
+            by_name
+                .into_iter()
+                .filter(|&(_, count)| count > 1)
+                .map(|(name, _)| Lint::DuplicateName(name.to_string())),
+        );
+
+        lints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::TaskNode;
+
+    fn ok_task() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn reports_orphans_and_duplicate_names() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), ok_task);
+        let b = TaskNode::new("b".to_string(), ok_task);
+        let b_again = TaskNode::new("b".to_string(), ok_task);
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(a.clone(), b_again);
+        dag.add_task(TaskNode::new("stray".to_string(), ok_task));
+
+        assert_eq!(
+            dag.lint(),
+            vec![
+                Lint::OrphanNode("stray".into()),
+                Lint::DuplicateName("b".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_root_and_leaf() {
+        let mut dag = Dag::new("g".into());
+        let a = TaskNode::new("a".to_string(), ok_task);
+        let b = TaskNode::new("b".to_string(), ok_task);
+        dag.add_task_relation(a.clone(), b.clone());
+        dag.add_task_relation(b, a);
+        assert_eq!(dag.lint(), vec![Lint::NoRoot, Lint::NoLeaf]);
+    }
+
+    #[test]
+    fn single_node_is_clean() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("only".to_string(), ok_task));
+        assert!(dag.lint().is_empty());
+    }
+}
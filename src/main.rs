@@ -1,30 +1,105 @@
-mod dag;
-mod task;
+use std::fs;
+use std::process::ExitCode;
 
-use dag::{Dag, TaskNode};
+use seadog_scheduler::dag::Dag;
 
-fn example_task_a() -> Result<(), String> {
-    println!("Task A");
-    Ok(())
+const USAGE: &str = "usage: seadog-scheduler run <file.yaml> [--dry-run] [--parallel N]";
+
+/// Options for the `run` subcommand.
+struct RunArgs {
+    path: String,
+    dry_run: bool,
+    parallel: Option<usize>,
 }
 
-fn example_task_b() -> Result<(), String> {
-    println!("Task B");
-    Ok(())
+fn parse_args(args: &[String]) -> Result<RunArgs, String> {
+    let mut args = args.iter();
+    match args.next().map(String::as_str) {
+        Some("run") => {}
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err("missing command".into()),
+    }
+
+    let mut path = None;
+    let mut dry_run = false;
+    let mut parallel = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--parallel" => {
+                let value = args.next().ok_or("--parallel needs a value")?;
+                let n = value
+                    .parse()
+                    .map_err(|_| format!("invalid --parallel value '{}'", value))?;
+                parallel = Some(n);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            file if path.is_none() => path = Some(file.to_string()),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+
+    Ok(RunArgs {
+        path: path.ok_or("missing DAG file")?,
+        dry_run,
+        parallel,
+    })
 }
 
-fn main() -> Result<(), String> {
-    let mut dag = Dag::new("example_dag".to_string());
-    let task_a = TaskNode::new("example_task_a".to_string(), example_task_a);
-    let task_b = TaskNode::new("example_task_b".to_string(), example_task_b);
-    dag.add_task_relation(task_a.clone(), task_b.clone());
+/// Prints the tasks in the order they would run, without running them
+///
+/// Fails when a task's program cannot be found, after flagging it in the listing.
+fn print_plan(dag: &Dag) -> Result<(), String> {
+    let mut missing = Vec::new();
+    for node in dag.dry_run()? {
+        let mut line = format!("{} ({})", node.name, node.task_type);
+        if !node.upstream.is_empty() {
+            line.push_str(&format!(" after {}", node.upstream.join(", ")));
+        }
+        if !node.executable_found {
+            line.push_str(" [executable not found]");
+            missing.push(node.name);
+        }
+        println!("{}", line);
+    }
+    if !missing.is_empty() {
+        return Err(format!("executable not found for: {}", missing.join(", ")));
+    }
+    Ok(())
+}
 
-    let result = dag.execute();
+fn run(args: RunArgs) -> Result<(), String> {
+    let src = fs::read_to_string(&args.path)
+        .map_err(|e| format!("cannot read '{}': {}", args.path, e))?;
+    let dag = Dag::from_yaml(&src)?;
 
-    match result {
-        Ok(_) => println!("All tasks completed successfully"),
-        Err(err) => println!("Error: {}", err),
+    if args.dry_run {
+        return print_plan(&dag);
     }
 
-    Ok(())
+    let (report, result) = match args.parallel {
+        Some(n) => dag.try_execute_parallel(n),
+        None => dag.try_execute(),
+    };
+    print!("{}", report);
+    result
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
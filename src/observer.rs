@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Progress notifications emitted while a DAG executes.
+///
+/// Every variant carries the name of the node it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// The node's task is about to run for the first time.
+    Started(String),
+    /// The node's task completed successfully.
+    Finished(String),
+    /// The node's task failed after exhausting its retries, with the error.
+    Failed(String, String),
+    /// The node's task failed and is about to be retried; the number counts retries from 1.
+    Retrying(String, u32),
+    /// The node was not run, with the reason.
+    Skipped(String, String),
+}
+
+/// Observer abstraction for execution progress
+pub trait DagObserver: Send + Sync {
+    fn on_event(&self, event: &ProgressEvent);
+}
+
+/// Blanket implementation so plain closures can be used as observers.
+impl<F> DagObserver for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ProgressEvent) {
+        (self)(event)
+    }
+}
+
+/// Observer that ignores every event.
+pub struct NoopObserver;
+
+impl DagObserver for NoopObserver {
+    fn on_event(&self, _event: &ProgressEvent) {}
+}
+
+/// One line of a `JsonLinesObserver` stream.
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    event: &'static str,
+    node: &'a str,
+    /// Milliseconds since the Unix epoch.
+    ts: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<u32>,
+}
+
+/// Observer that writes each event as one JSON object per line
+///
+/// Lines look like `{"event":"start","node":"a","ts":1700000000000}`; failures add an
+/// `error`, skips a `reason` and retries a `retry` count. Write errors are ignored so a
+/// broken log sink cannot fail the run.
+pub struct JsonLinesObserver<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesObserver<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesObserver {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<W: Write + Send> DagObserver for JsonLinesObserver<W> {
+    fn on_event(&self, event: &ProgressEvent) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis());
+        let record = |event, node| EventRecord {
+            event,
+            node,
+            ts,
+            error: None,
+            reason: None,
+            retry: None,
+        };
+        let record = match event {
+            ProgressEvent::Started(node) => record("start", node),
+            ProgressEvent::Finished(node) => record("finish", node),
+            ProgressEvent::Failed(node, error) => EventRecord {
+                error: Some(error),
+                ..record("fail", node)
+            },
+            ProgressEvent::Retrying(node, retry) => EventRecord {
+                retry: Some(*retry),
+                ..record("retry", node)
+            },
+            ProgressEvent::Skipped(node, reason) => EventRecord {
+                reason: Some(reason),
+                ..record("skip", node)
+            },
+        };
+
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if serde_json::to_writer(&mut *writer, &record).is_ok() {
+            let _ = writer.write_all(b"\n");
+        }
+    }
+}
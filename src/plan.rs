@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dag::{Dag, SortStrategy, TaskNode};
+use crate::executor::RunSettings;
+use crate::observer::NoopObserver;
+use crate::report::ExecutionReport;
+
+/// A frozen execution order, saved so a later run can follow it exactly
+///
+/// Plans refer to nodes by id, so they only stay meaningful for DAGs whose ids are
+/// stable, such as those built with `TaskNode::new_hashed` or loaded from YAML.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    /// Name of the DAG the plan was made for.
+    pub dag: String,
+    /// Node ids in the order they run.
+    pub node_ids: Vec<String>,
+}
+
+impl ExecutionPlan {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("plans always serialize")
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, String> {
+        serde_json::from_str(src).map_err(|e| format!("invalid execution plan: {}", e))
+    }
+}
+
+/// What a dry run reports for one node, without running it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DryRunNode {
+    pub name: String,
+    pub task_type: String,
+    /// Names of the node's direct upstream nodes.
+    pub upstream: Vec<String>,
+    /// Whether the program the task starts is on `PATH`; always true for tasks that
+    /// start none. See `Task::executable_found`.
+    pub executable_found: bool,
+}
+
+impl Dag {
+    /// The nodes in the order they would run, with the checks a dry run makes
+    ///
+    /// Nothing is executed; subprocess tasks only have their program looked up.
+    pub fn dry_run(&self) -> Result<Vec<DryRunNode>, String> {
+        Ok(self
+            .resolve_execution_order()?
+            .iter()
+            .map(|node| DryRunNode {
+                name: node.name.clone(),
+                task_type: node.task_type().to_string(),
+                upstream: self
+                    .predecessors(node)
+                    .iter()
+                    .map(|upstream| upstream.name.clone())
+                    .collect(),
+                executable_found: node.executable_found(),
+            })
+            .collect())
+    }
+
+    /// Freezes the current execution order into a plan.
+    pub fn plan(&self) -> Result<ExecutionPlan, String> {
+        self.plan_with(SortStrategy::KahnBfs)
+    }
+
+    /// Like `plan`, ordering ready nodes with `strategy`.
+    pub fn plan_with(&self, strategy: SortStrategy) -> Result<ExecutionPlan, String> {
+        Ok(ExecutionPlan {
+            dag: self.name().to_string(),
+            node_ids: self
+                .resolve_execution_order_with(strategy)?
+                .iter()
+                .map(|node| node.id().to_string())
+                .collect(),
+        })
+    }
+
+    /// Runs the nodes sequentially in exactly the order of `plan`; see `try_execute_plan`.
+    pub fn execute_plan(&self, plan: &ExecutionPlan) -> Result<(), String> {
+        self.try_execute_plan(plan).1
+    }
+
+    /// Like `try_execute`, following `plan` instead of sorting the graph
+    ///
+    /// The plan must name every node of the DAG once and put each node after all of its
+    /// upstream nodes; otherwise nothing runs. Ids of nodes that are no longer in the
+    /// DAG are ignored.
+    pub fn try_execute_plan(&self, plan: &ExecutionPlan) -> (ExecutionReport, Result<(), String>) {
+        match self.order_from_plan(plan) {
+            Ok(order) => self.run_sequential_in_order(&RunSettings::new(&NoopObserver), &order),
+            Err(err) => (ExecutionReport::default(), Err(err)),
+        }
+    }
+
+    /// Whether running the nodes called `names`, in that order, respects every
+    /// dependency among them, so the run could be continued to a full execution order
+    ///
+    /// Each node must come after all of its upstream nodes. A name shared by several
+    /// nodes stands for all of them at once. Unknown or repeated names make the prefix
+    /// invalid; an empty prefix is valid.
+    pub fn is_valid_prefix(&self, names: &[&str]) -> bool {
+        let mut placed: HashSet<&str> = HashSet::new();
+        for &name in names {
+            if !placed.insert(name) {
+                return false;
+            }
+            let mut nodes = self
+                .get_all_tasks()
+                .into_iter()
+                .filter(|node| node.name == name)
+                .peekable();
+            if nodes.peek().is_none() {
+                return false;
+            }
+            let ready = nodes.all(|node| {
+                self.predecessors(&node)
+                    .iter()
+                    .all(|upstream| placed.contains(upstream.name.as_str()))
+            });
+            if !ready {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn order_from_plan(&self, plan: &ExecutionPlan) -> Result<Vec<TaskNode>, String> {
+        let nodes = self.get_all_tasks();
+        let by_id: HashMap<&str, &TaskNode> = nodes.iter().map(|node| (node.id(), node)).collect();
+
+        let mut order: Vec<TaskNode> = Vec::with_capacity(nodes.len());
+        let mut placed: HashSet<&str> = HashSet::new();
+        for id in &plan.node_ids {
+            let Some(&node) = by_id.get(id.as_str()) else {
+                continue;
+            };
+            if !placed.insert(node.id()) {
+                return Err(format!("plan lists task '{}' more than once", node.name));
+            }
+            if let Some(upstream) = self
+                .predecessors(node)
+                .iter()
+                .find(|upstream| !placed.contains(upstream.id()))
+            {
+                return Err(format!(
+                    "plan runs task '{}' before its upstream task '{}'",
+                    node.name, upstream.name
+                ));
+            }
+            order.push(node.clone());
+        }
+
+        if let Some(missing) = nodes.iter().find(|node| !placed.contains(node.id())) {
+            return Err(format!("plan is missing task '{}'", missing.name));
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn recording_dag(log: &Arc<Mutex<Vec<String>>>) -> Dag {
+        // root -> (a1 -> a2), root -> b1
+        let node = |name: &str| {
+            let (log, name) = (log.clone(), name.to_string());
+            TaskNode::new_hashed(name.clone(), move || {
+                log.lock().unwrap().push(name.clone());
+                Ok(())
+            })
+        };
+        let (root, a1, a2, b1) = (node("root"), node("a1"), node("a2"), node("b1"));
+        let mut dag = Dag::new("plan".into());
+        dag.add_task_relation(root.clone(), a1.clone());
+        dag.add_task_relation(root, b1);
+        dag.add_task_relation(a1, a2);
+        dag
+    }
+
+    #[test]
+    fn round_tripped_plan_runs_in_the_same_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let plan = recording_dag(&log)
+            .plan_with(SortStrategy::DepthFirst)
+            .unwrap();
+        let loaded = ExecutionPlan::from_json(&plan.to_json()).unwrap();
+        assert_eq!(loaded, plan);
+
+        // A freshly built DAG gets the same hashed ids
+        let dag = recording_dag(&log);
+        assert_eq!(dag.execute_plan(&loaded), Ok(()));
+        assert_eq!(*log.lock().unwrap(), vec!["root", "a1", "a2", "b1"]);
+        let names: Vec<String> = dag
+            .resolve_execution_order_with(SortStrategy::DepthFirst)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.name)
+            .collect();
+        assert_eq!(*log.lock().unwrap(), names);
+    }
+
+    #[test]
+    fn invalid_plans_are_rejected() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let dag = recording_dag(&log);
+        let mut plan = dag.plan().unwrap();
+
+        let mut reversed = plan.clone();
+        reversed.node_ids.reverse();
+        assert!(
+            dag.execute_plan(&reversed)
+                .unwrap_err()
+                .contains("before its upstream")
+        );
+
+        let last = plan.node_ids.pop().unwrap();
+        assert!(
+            dag.execute_plan(&plan)
+                .unwrap_err()
+                .starts_with("plan is missing task")
+        );
+
+        plan.node_ids.push("no-such-node".into());
+        plan.node_ids.push(last);
+        assert_eq!(dag.execute_plan(&plan), Ok(()));
+        assert!(ExecutionPlan::from_json("{").is_err());
+    }
+
+    #[test]
+    fn dry_run_flags_missing_executables() {
+        use crate::task::{PythonTask, ShellTask};
+
+        let mut dag = Dag::new("tools".into());
+        let fetch = TaskNode::new("fetch".to_string(), ShellTask::new("FOO=1 sh -c true"));
+        let convert = TaskNode::new(
+            "convert".to_string(),
+            ShellTask::new("no-such-tool-seadog --fast input.csv"),
+        );
+        let script = TaskNode::new(
+            "script".to_string(),
+            PythonTask::with_interpreter("print(1)", "/no/such/python"),
+        );
+        let builtin = TaskNode::new("enter".to_string(), ShellTask::new("cd /tmp && ls"));
+        let native = TaskNode::new("native".to_string(), || Ok(()));
+        dag.add_task_relation(fetch.clone(), convert);
+        dag.add_task_relation(fetch, script);
+        dag.add_task(builtin);
+        dag.add_task(native);
+
+        let report = dag.dry_run().unwrap();
+        let found = |name: &str| {
+            report
+                .iter()
+                .find(|node| node.name == name)
+                .unwrap()
+                .executable_found
+        };
+        assert!(found("fetch") && found("enter") && found("native"));
+        assert!(!found("convert"));
+        assert!(!found("script"));
+        let convert = report.iter().find(|node| node.name == "convert").unwrap();
+        assert_eq!(convert.upstream, vec!["fetch"]);
+        assert_eq!(convert.task_type, "shell");
+    }
+
+    #[test]
+    fn valid_prefixes_respect_dependencies() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let dag = recording_dag(&log);
+        assert!(dag.is_valid_prefix(&[]));
+        assert!(dag.is_valid_prefix(&["root", "b1"]));
+        assert!(dag.is_valid_prefix(&["root", "a1", "b1", "a2"]));
+
+        assert!(!dag.is_valid_prefix(&["root", "a2"]));
+        assert!(!dag.is_valid_prefix(&["a1"]));
+        assert!(!dag.is_valid_prefix(&["root", "root"]));
+        assert!(!dag.is_valid_prefix(&["root", "missing"]));
+    }
+}
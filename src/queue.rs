@@ -0,0 +1,118 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dag::{Dag, TaskNode};
+
+/// What is left of a run, as saved on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueState {
+    /// Name of the DAG being run.
+    dag: String,
+    /// Nodes that have not started, or that failed and have to run again.
+    pending: BTreeSet<String>,
+    /// Nodes whose tasks were running when the file was last saved.
+    in_flight: BTreeSet<String>,
+    /// Nodes that succeeded.
+    done: BTreeSet<String>,
+}
+
+/// The live run queue of a scheduler, saved to a JSON file after every change
+///
+/// Nodes are keyed by name, so node names should be unique. See
+/// `Scheduler::with_queue_file` and `Scheduler::recover`.
+#[derive(Debug)]
+pub(crate) struct RunQueue {
+    path: PathBuf,
+    state: Mutex<QueueState>,
+}
+
+impl RunQueue {
+    /// Starts a fresh queue holding every main node of `dag`.
+    pub(crate) fn create(path: &Path, dag: &Dag) -> Result<Self, String> {
+        let queue = RunQueue {
+            path: path.to_path_buf(),
+            state: Mutex::new(QueueState {
+                dag: dag.name().to_string(),
+                pending: dag
+                    .get_all_tasks()
+                    .into_iter()
+                    .map(|node| node.name)
+                    .collect(),
+                ..QueueState::default()
+            }),
+        };
+        queue.save(&queue.lock())?;
+        Ok(queue)
+    }
+
+    /// Loads the queue an earlier run of `dag` left at `path`
+    ///
+    /// Nodes that were in flight go back to pending, since their tasks never finished.
+    pub(crate) fn load(path: &Path, dag: &Dag) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read run queue {}: {}", path.display(), e))?;
+        let mut state: QueueState = serde_json::from_str(&text)
+            .map_err(|e| format!("invalid run queue {}: {}", path.display(), e))?;
+        if state.dag != dag.name() {
+            return Err(format!(
+                "run queue {} is for DAG '{}', not '{}'",
+                path.display(),
+                state.dag,
+                dag.name()
+            ));
+        }
+        let interrupted = std::mem::take(&mut state.in_flight);
+        state.pending.extend(interrupted);
+        Ok(RunQueue {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Whether `node` already succeeded in the run this queue belongs to.
+    pub(crate) fn is_done(&self, node: &TaskNode) -> bool {
+        self.lock().done.contains(&node.name)
+    }
+
+    /// Moves `node` from pending to in flight.
+    pub(crate) fn start(&self, node: &TaskNode) -> Result<(), String> {
+        let mut state = self.lock();
+        state.pending.remove(&node.name);
+        state.in_flight.insert(node.name.clone());
+        self.save(&state)
+    }
+
+    /// Moves `node` from in flight to done, or back to pending when it failed.
+    pub(crate) fn finish(&self, node: &TaskNode, succeeded: bool) -> Result<(), String> {
+        let mut state = self.lock();
+        state.in_flight.remove(&node.name);
+        if succeeded {
+            state.done.insert(node.name.clone());
+        } else {
+            state.pending.insert(node.name.clone());
+        }
+        self.save(&state)
+    }
+
+    /// Writes `state` to a sibling temp file and renames it over the queue file,
+    /// so a crash mid-write leaves the previous file intact.
+    fn save(&self, state: &QueueState) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(state).expect("run queues always serialize");
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, text)
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("could not save run queue {}: {}", self.path.display(), e))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
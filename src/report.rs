@@ -0,0 +1,323 @@
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+/// Outcome of a single node within a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeStatus {
+    /// The node has not run yet.
+    Pending,
+    Success,
+    /// The node's task failed, with the error.
+    Failed(String),
+    /// The node was not run, with the reason.
+    Skipped(String),
+}
+
+impl NodeStatus {
+    /// Whether the node has reached a final state (success, failure or skip).
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, NodeStatus::Pending)
+    }
+}
+
+/// Per-node entry of an `ExecutionReport`.
+#[derive(Debug, Clone)]
+pub struct NodeReport {
+    pub name: String,
+    pub status: NodeStatus,
+    /// Wall-clock time spent running the task, retries included.
+    pub duration: Duration,
+    /// Non-fatal warnings the task reported on success.
+    pub warnings: Vec<String>,
+    /// Exit status of the task's subprocess; `None` for tasks that do not run one.
+    pub exit_code: Option<i32>,
+}
+
+/// Summary of a DAG run, with one entry per node in execution order.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionReport {
+    pub nodes: Vec<NodeReport>,
+    /// Teardown tasks in the order they ran; kept apart from the main nodes.
+    pub teardown: Vec<NodeReport>,
+}
+
+impl ExecutionReport {
+    /// Every warning reported by a main node, as `(node name, warning)` in run order.
+    pub fn warnings(&self) -> Vec<(&str, &str)> {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                node.warnings
+                    .iter()
+                    .map(move |warning| (node.name.as_str(), warning.as_str()))
+            })
+            .collect()
+    }
+
+    /// Entry for the first node called `name`.
+    pub fn get(&self, name: &str) -> Option<&NodeReport> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Status of the first node called `name`.
+    pub fn status(&self, name: &str) -> Option<&NodeStatus> {
+        self.get(name).map(|node| &node.status)
+    }
+
+    /// Number of main nodes that succeeded.
+    pub fn success_count(&self) -> usize {
+        self.count(|status| matches!(status, NodeStatus::Success))
+    }
+
+    /// Number of main nodes whose task failed.
+    pub fn failure_count(&self) -> usize {
+        self.count(|status| matches!(status, NodeStatus::Failed(_)))
+    }
+
+    /// Number of main nodes that were skipped.
+    pub fn skipped_count(&self) -> usize {
+        self.count(|status| matches!(status, NodeStatus::Skipped(_)))
+    }
+
+    fn count(&self, matches: impl Fn(&NodeStatus) -> bool) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| matches(&node.status))
+            .count()
+    }
+
+    /// Fraction of nodes, between 0.0 and 1.0, that have reached a terminal state.
+    ///
+    /// An empty report counts as fully complete.
+    pub fn progress(&self) -> f32 {
+        if self.nodes.is_empty() {
+            return 1.0;
+        }
+        let done = self
+            .nodes
+            .iter()
+            .filter(|node| node.status.is_terminal())
+            .count();
+        done as f32 / self.nodes.len() as f32
+    }
+
+    /// The report as a JUnit XML test suite, for CI systems that display test results
+    ///
+    /// Each node becomes a `<testcase>` with its duration in seconds. Failed nodes get
+    /// a `<failure>` carrying the error, and skipped or pending nodes a `<skipped>`.
+    /// Teardown tasks follow the main nodes with the class name `teardown`.
+    pub fn to_junit_xml(&self) -> String {
+        let all = || self.nodes.iter().chain(&self.teardown);
+        let seconds = |duration: Duration| format!("{:.3}", duration.as_secs_f64());
+        let failures = all()
+            .filter(|node| matches!(node.status, NodeStatus::Failed(_)))
+            .count();
+        let skipped = all()
+            .filter(|node| !matches!(node.status, NodeStatus::Success | NodeStatus::Failed(_)))
+            .count();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuite name=\"dag\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">",
+            all().count(),
+            failures,
+            skipped,
+            seconds(all().map(|node| node.duration).sum())
+        );
+        let cases = self
+            .nodes
+            .iter()
+            .map(|node| (node, None))
+            .chain(self.teardown.iter().map(|node| (node, Some("teardown"))));
+        for (node, class) in cases {
+            let class = class.map_or(String::new(), |class| format!(" classname=\"{}\"", class));
+            let _ = write!(
+                xml,
+                "  <testcase name=\"{}\"{} time=\"{}\"",
+                escape_xml(&node.name),
+                class,
+                seconds(node.duration)
+            );
+            let (element, message) = match &node.status {
+                NodeStatus::Success => {
+                    xml.push_str("/>\n");
+                    continue;
+                }
+                NodeStatus::Failed(err) => ("failure", err.as_str()),
+                NodeStatus::Skipped(reason) => ("skipped", reason.as_str()),
+                NodeStatus::Pending => ("skipped", "not run"),
+            };
+            let message = escape_xml(message);
+            let _ = writeln!(
+                xml,
+                ">\n    <{element} message=\"{message}\">{message}</{element}>\n  </testcase>"
+            );
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+/// Escapes text for use in XML content and attribute values.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeStatus::Pending => write!(f, "pending"),
+            NodeStatus::Success => write!(f, "success"),
+            NodeStatus::Failed(err) => write!(f, "failed: {}", err),
+            NodeStatus::Skipped(reason) => write!(f, "skipped: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for NodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{:?}] {}", self.name, self.duration, self.status)?;
+        for warning in &self.warnings {
+            write!(f, " (warning: {})", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per node, followed by the teardown tasks if there are any.
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            writeln!(f, "{}", node)?;
+        }
+        if !self.teardown.is_empty() {
+            writeln!(f, "teardown:")?;
+            for node in &self.teardown {
+                writeln!(f, "  {}", node)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, status: NodeStatus) -> NodeReport {
+        NodeReport {
+            name: name.to_string(),
+            status,
+            duration: Duration::ZERO,
+            warnings: Vec::new(),
+            exit_code: None,
+        }
+    }
+
+    #[test]
+    fn progress_counts_terminal_states() {
+        let mut report = ExecutionReport {
+            nodes: vec![
+                entry("a", NodeStatus::Success),
+                entry("b", NodeStatus::Failed("boom".into())),
+                entry("c", NodeStatus::Pending),
+                entry("d", NodeStatus::Pending),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(report.progress(), 0.5);
+
+        report.nodes[2].status = NodeStatus::Skipped("upstream failed".into());
+        assert_eq!(report.progress(), 0.75);
+        assert_eq!(ExecutionReport::default().progress(), 1.0);
+    }
+
+    #[test]
+    fn counts_each_outcome_of_a_run() {
+        use crate::dag::{Dag, TaskNode};
+
+        let mut dag = Dag::new("mixed".into());
+        let ok = || TaskNode::new("ok".to_string(), || Ok(()));
+        let (first, second) = (ok(), ok());
+        let broken = TaskNode::new("broken".to_string(), || Err("boom".to_string()));
+        dag.add_task_relation(first, second.clone());
+        dag.add_task_relation(second, broken.clone());
+        dag.add_task_relation(
+            broken.clone(),
+            TaskNode::new("after".to_string(), || Ok(())),
+        );
+        dag.add_task_relation(broken, TaskNode::new("report".to_string(), || Ok(())));
+
+        let (report, result) = dag.try_execute();
+        assert!(result.is_err());
+        assert_eq!(report.success_count(), 2);
+        assert_eq!(report.failure_count(), 1);
+        assert_eq!(report.skipped_count(), 2);
+        assert_eq!(ExecutionReport::default().success_count(), 0);
+    }
+
+    #[test]
+    fn junit_xml_has_a_testcase_per_node() {
+        let mut slow = entry("load", NodeStatus::Success);
+        slow.duration = Duration::from_millis(1500);
+        let report = ExecutionReport {
+            nodes: vec![
+                slow,
+                entry(
+                    "check",
+                    NodeStatus::Failed("expected <3> & got \"4\"".into()),
+                ),
+                entry(
+                    "publish",
+                    NodeStatus::Skipped("upstream task 'check' failed".into()),
+                ),
+            ],
+            teardown: vec![entry("cleanup", NodeStatus::Success)],
+        };
+        let xml = report.to_junit_xml();
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="dag" tests="4" failures="1" skipped="1" time="1.500">
+  <testcase name="load" time="1.500"/>
+  <testcase name="check" time="0.000">
+    <failure message="expected &lt;3&gt; &amp; got &quot;4&quot;">expected &lt;3&gt; &amp; got &quot;4&quot;</failure>
+  </testcase>
+  <testcase name="publish" time="0.000">
+    <skipped message="upstream task &apos;check&apos; failed">upstream task &apos;check&apos; failed</skipped>
+  </testcase>
+  <testcase name="cleanup" classname="teardown" time="0.000"/>
+</testsuite>
+"#
+        );
+        assert_eq!(xml.matches("<testcase ").count(), 4);
+        assert_eq!(xml.matches("<failure ").count(), 1);
+    }
+
+    #[test]
+    fn display_lists_each_node() {
+        let report = ExecutionReport {
+            nodes: vec![
+                entry("a", NodeStatus::Success),
+                entry("b", NodeStatus::Failed("boom".into())),
+            ],
+            teardown: vec![entry("cleanup", NodeStatus::Success)],
+        };
+        assert_eq!(
+            report.to_string(),
+            "a [0ns] success\nb [0ns] failed: boom\nteardown:\n  cleanup [0ns] success\n"
+        );
+    }
+}
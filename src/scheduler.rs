@@ -0,0 +1,856 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::control::{CancelToken, PauseGate, RetryLimiter};
+use crate::dag::{Dag, SortStrategy};
+use crate::executor::{RunSettings, SchedulingState, TeardownPolicy};
+use crate::observer::NoopObserver;
+use crate::queue::RunQueue;
+use crate::report::{ExecutionReport, NodeStatus};
+
+/// Number of recent runs kept by `Scheduler::history`.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// One finished run of a scheduler's DAG.
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub report: ExecutionReport,
+    pub result: Result<(), String>,
+    pub duration: Duration,
+}
+
+/// Counters over every run, including those dropped from the history.
+#[derive(Debug, Default)]
+struct Totals {
+    runs: u64,
+    task_successes: u64,
+    task_failures: u64,
+    last_run_duration: Option<Duration>,
+}
+
+/// Owns a DAG and coordinates its runs
+///
+/// A scheduler can be shared between threads, but only one run may be in progress at
+/// a time; a concurrent call to `execute` is rejected instead of racing the first.
+pub struct Scheduler {
+    dag: Dag,
+    running: AtomicBool,
+    history: Mutex<VecDeque<RunRecord>>,
+    totals: Mutex<Totals>,
+    gate: PauseGate,
+    default_task_timeout: Option<Duration>,
+    running_nodes: Arc<Mutex<HashSet<String>>>,
+    finished_nodes: Mutex<HashSet<String>>,
+    teardown_policy: TeardownPolicy,
+    scheduling_state: Mutex<SchedulingState>,
+    retry_limiter: Option<Arc<RetryLimiter>>,
+    log_dir: Option<PathBuf>,
+    cancel: Option<CancelToken>,
+    queue_file: Option<PathBuf>,
+}
+
+/// Clears the running flag when a run ends, even if a task panics.
+struct RunGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl Scheduler {
+    pub fn new(dag: Dag) -> Self {
+        Scheduler {
+            dag,
+            running: AtomicBool::new(false),
+            history: Mutex::new(VecDeque::new()),
+            totals: Mutex::new(Totals::default()),
+            gate: PauseGate::new(),
+            default_task_timeout: None,
+            running_nodes: Arc::new(Mutex::new(HashSet::new())),
+            finished_nodes: Mutex::new(HashSet::new()),
+            teardown_policy: TeardownPolicy::Gather,
+            scheduling_state: Mutex::new(SchedulingState::default()),
+            retry_limiter: None,
+            log_dir: None,
+            cancel: None,
+            queue_file: None,
+        }
+    }
+
+    /// Chooses whether teardown stops at its first failure; defaults to `Gather`
+    ///
+    /// Teardown outcomes are recorded in each run's report and never change the result
+    /// of `execute`.
+    pub fn with_teardown_policy(mut self, policy: TeardownPolicy) -> Self {
+        self.teardown_policy = policy;
+        self
+    }
+
+    /// Bounds every task attempt that has no timeout of its own
+    ///
+    /// A node's `with_timeout` overrides this default.
+    pub fn with_default_task_timeout(mut self, timeout: Duration) -> Self {
+        self.default_task_timeout = Some(timeout);
+        self
+    }
+
+    /// Throttles node retries (`TaskNode::with_retries`) across every run of this scheduler
+    ///
+    /// Give the same limiter to `RetryTask::with_limiter` to count those retries too.
+    pub fn with_retry_limiter(mut self, limiter: Arc<RetryLimiter>) -> Self {
+        self.retry_limiter = Some(limiter);
+        self
+    }
+
+    /// Sends each subprocess task's stdout and stderr to `{dir}/{node name}.log`
+    ///
+    /// Output is appended, so a log keeps every run. Tasks that capture output, or that
+    /// check it with `ShellTask::success_if`, still get all of it as well. Characters
+    /// of the node name that could leave `dir`, such as `/`, are written as `_`. The
+    /// directory is created on first use; failing to open a log fails the task.
+    pub fn with_log_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Stops starting nodes, and node retries, once `cancel` is cancelled
+    ///
+    /// Nodes that have not started fail with "run cancelled", and the processes of
+    /// subprocess tasks that are already running are killed.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Saves the run queue to `path` as each node starts and finishes
+    ///
+    /// The JSON file lists the nodes that are pending, in flight and done, so that
+    /// after a crash `recover` can finish the remaining work. Each run starts a fresh
+    /// queue, and failing to save it fails the node. Nodes are tracked by name, so
+    /// names should be unique.
+    pub fn with_queue_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.queue_file = Some(path.into());
+        self
+    }
+
+    pub fn queue_file(&self) -> Option<&Path> {
+        self.queue_file.as_deref()
+    }
+
+    pub fn log_dir(&self) -> Option<&Path> {
+        self.log_dir.as_deref()
+    }
+
+    pub fn default_task_timeout(&self) -> Option<Duration> {
+        self.default_task_timeout
+    }
+
+    pub fn dag(&self) -> &Dag {
+        &self.dag
+    }
+
+    /// Whether a run is currently in progress.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub fn execute(&self) -> Result<(), String> {
+        self.run(None, |dag, settings| dag.run_sequential(settings))
+    }
+
+    /// Resumes the run whose queue was saved at `path`; see `with_queue_file`
+    ///
+    /// Runs the DAG sequentially, skipping the nodes the queue lists as done. Nodes
+    /// that were pending or in flight run again, and the queue at `path` is kept up to
+    /// date, so a recovery that crashes too can itself be recovered. The file must
+    /// come from a run of a DAG with the same name.
+    pub fn recover<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        self.run(Some(path.as_ref()), |dag, settings| {
+            dag.run_sequential(settings)
+        })
+    }
+
+    /// Runs the DAG with at most `max_concurrency` tasks in flight; see `Dag::execute_parallel`.
+    pub fn execute_parallel(&self, max_concurrency: usize) -> Result<(), String> {
+        if max_concurrency == 0 {
+            return Err("max_concurrency must be at least 1".into());
+        }
+        self.run(None, |dag, settings| {
+            dag.run_parallel(settings, max_concurrency, SortStrategy::KahnBfs)
+        })
+    }
+
+    /// Names of the nodes whose tasks are running at this moment
+    ///
+    /// The handle stays valid across runs and can be polled from another thread, e.g.
+    /// by a status view; it is empty between runs.
+    pub fn running_nodes(&self) -> Arc<Mutex<HashSet<String>>> {
+        self.running_nodes.clone()
+    }
+
+    /// Readable snapshot of an in-progress parallel run
+    ///
+    /// Lists the nodes in flight, the ready nodes not yet dispatched, and how many
+    /// upstream nodes each waiting node still needs. The snapshot is taken each time the
+    /// run waits for a task, so it shows where a stuck run is waiting. Sequential runs
+    /// and idle schedulers report nothing in flight.
+    pub fn debug_state(&self) -> String {
+        self.lock_state().to_string()
+    }
+
+    /// Rough time left in the current run, from the nodes' estimated durations
+    ///
+    /// The longest chain of `TaskNode::with_estimated_duration` estimates through the
+    /// nodes that have not finished yet. A running node counts its full estimate, and
+    /// nodes without one count as instant. `None` between runs.
+    pub fn eta(&self) -> Option<Duration> {
+        if !self.is_running() {
+            return None;
+        }
+        let finished = self.lock_finished();
+        self.dag
+            .remaining_time(|node| finished.contains(&node.name))
+            .ok()
+    }
+
+    fn lock_finished(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.finished_nodes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, SchedulingState> {
+        self.scheduling_state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs the DAG with `engine`, continuing the queue saved at `recover_from` if given.
+    fn run(
+        &self,
+        recover_from: Option<&Path>,
+        engine: impl FnOnce(&Dag, &RunSettings<'_>) -> (ExecutionReport, Result<(), String>),
+    ) -> Result<(), String> {
+        let _guard = self.begin_run()?;
+        let queue = match (recover_from, &self.queue_file) {
+            (Some(path), _) => Some(RunQueue::load(path, &self.dag)?),
+            (None, Some(path)) => Some(RunQueue::create(path, &self.dag)?),
+            (None, None) => None,
+        };
+        let started = Instant::now();
+        self.lock_finished().clear();
+        let settings = RunSettings {
+            gate: Some(&self.gate),
+            default_timeout: self.default_task_timeout,
+            running: Some(&self.running_nodes),
+            finished: Some(&self.finished_nodes),
+            teardown_policy: self.teardown_policy,
+            state: Some(&self.scheduling_state),
+            retry_limiter: self.retry_limiter.as_deref(),
+            log_dir: self.log_dir.as_deref(),
+            cancel: self.cancel.as_ref(),
+            queue: queue.as_ref(),
+            ..RunSettings::new(&NoopObserver)
+        };
+        let (report, result) = engine(&self.dag, &settings);
+        *self.lock_state() = SchedulingState::default();
+        self.record(RunRecord {
+            report,
+            result: result.clone(),
+            duration: started.elapsed(),
+        });
+        result
+    }
+
+    /// Holds back tasks that have not started yet until `resume` is called
+    ///
+    /// A task already running is not interrupted, and a run started while paused waits
+    /// before its first task. Teardown tasks are not held back.
+    pub fn pause(&self) {
+        self.gate.pause();
+    }
+
+    pub fn resume(&self) {
+        self.gate.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.gate.is_paused()
+    }
+
+    /// The most recent runs, oldest first, up to `HISTORY_LIMIT`.
+    pub fn history(&self) -> Vec<RunRecord> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Run counters in the Prometheus text exposition format
+    ///
+    /// Every sample carries a `dag` label with the DAG's name. Task counts cover main
+    /// nodes only; teardown tasks are not included.
+    pub fn metrics_text(&self) -> String {
+        let totals = self.totals.lock().unwrap();
+        let label = format!("{{dag=\"{}\"}}", escape_label(self.dag.name()));
+        let last_run = totals
+            .last_run_duration
+            .map_or(0.0, |duration| duration.as_secs_f64());
+
+        let metrics: [(&str, &str, &str, String); 4] = [
+            (
+                "seadog_runs_total",
+                "counter",
+                "Total number of DAG runs.",
+                totals.runs.to_string(),
+            ),
+            (
+                "seadog_task_successes_total",
+                "counter",
+                "Total number of tasks that succeeded.",
+                totals.task_successes.to_string(),
+            ),
+            (
+                "seadog_task_failures_total",
+                "counter",
+                "Total number of tasks that failed.",
+                totals.task_failures.to_string(),
+            ),
+            (
+                "seadog_last_run_duration_seconds",
+                "gauge",
+                "Wall-clock duration of the most recent run.",
+                last_run.to_string(),
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{}{} {}", name, label, value);
+        }
+        out
+    }
+
+    fn record(&self, run: RunRecord) {
+        {
+            let mut totals = self.totals.lock().unwrap();
+            totals.runs += 1;
+            for node in &run.report.nodes {
+                match node.status {
+                    NodeStatus::Success => totals.task_successes += 1,
+                    NodeStatus::Failed(_) => totals.task_failures += 1,
+                    _ => {}
+                }
+            }
+            totals.last_run_duration = Some(run.duration);
+        }
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(run);
+    }
+
+    fn begin_run(&self) -> Result<RunGuard<'_>, String> {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .map_err(|_| String::from("execution already in progress"))?;
+        Ok(RunGuard(&self.running))
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dag::TaskNode;
+    use crate::report::NodeReport;
+    use std::sync::Barrier;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn concurrent_execute_is_rejected() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("slow".to_string(), || {
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        }));
+        let scheduler = Scheduler::new(dag);
+        let barrier = Barrier::new(2);
+
+        let results: Vec<Result<(), String>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        scheduler.execute()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let rejected = results
+            .iter()
+            .filter(|r| {
+                r.as_ref().err().map(String::as_str) == Some("execution already in progress")
+            })
+            .count();
+        assert_eq!(rejected, 1);
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+
+        // The guard is released once the run finishes
+        assert!(!scheduler.is_running());
+        assert_eq!(scheduler.execute(), Ok(()));
+    }
+
+    #[test]
+    fn metrics_text_counts_runs_and_tasks() {
+        let mut dag = Dag::new("etl \"nightly\"".into());
+        let a = TaskNode::new("a".to_string(), || Ok(()));
+        let b = TaskNode::new("b".to_string(), || Err("boom".to_string()));
+        dag.add_task(a.clone());
+        dag.add_task(b.clone());
+        let scheduler = Scheduler::new(dag);
+        assert!(scheduler.execute().is_err());
+        assert!(scheduler.execute().is_err());
+
+        let text = scheduler.metrics_text();
+        let label = r#"{dag="etl \"nightly\""}"#;
+        assert!(text.contains(&format!("seadog_runs_total{} 2\n", label)));
+        assert!(text.contains(&format!("seadog_task_successes_total{} ", label)));
+        assert!(text.contains(&format!("seadog_task_failures_total{} 2\n", label)));
+        assert!(text.contains("# TYPE seadog_last_run_duration_seconds gauge\n"));
+        assert_eq!(scheduler.history().len(), 2);
+
+        // Every sample follows a TYPE line for its metric and has a numeric value
+        let mut typed = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(kind == "counter" || kind == "gauge");
+                typed.push(name.to_string());
+            } else if !line.starts_with("# HELP ") {
+                let (series, value) = line.rsplit_once(' ').unwrap();
+                let name = &series[..series.find('{').unwrap()];
+                assert!(series.ends_with('}'));
+                assert_eq!(typed.last().map(String::as_str), Some(name));
+                assert!(value.parse::<f64>().is_ok(), "{}", line);
+            }
+        }
+        assert_eq!(typed.len(), 4);
+    }
+
+    #[test]
+    fn pause_holds_back_new_tasks_until_resumed() {
+        let started = Arc::new(Barrier::new(2));
+        let release = Arc::new(Barrier::new(2));
+        let b_started = Arc::new(AtomicBool::new(false));
+
+        let mut dag = Dag::new("g".into());
+        let a = {
+            let (started, release) = (started.clone(), release.clone());
+            TaskNode::new("a".to_string(), move || {
+                started.wait();
+                release.wait();
+                Ok(())
+            })
+        };
+        let b = {
+            let b_started = b_started.clone();
+            TaskNode::new("b".to_string(), move || {
+                b_started.store(true, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+        dag.add_task_relation(a, b);
+        let scheduler = Scheduler::new(dag);
+
+        thread::scope(|scope| {
+            let run = scope.spawn(|| scheduler.execute());
+            started.wait();
+            scheduler.pause();
+            // `a` is in flight and finishes while paused
+            release.wait();
+            thread::sleep(Duration::from_millis(100));
+            assert!(!b_started.load(Ordering::SeqCst));
+            assert!(scheduler.is_running());
+
+            scheduler.resume();
+            assert_eq!(run.join().unwrap(), Ok(()));
+        });
+        assert!(b_started.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn retry_limiter_throttles_node_retries() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let limiter = RetryLimiter::new(1, Duration::from_secs(1)).with_clock(clock.clone());
+        let mut dag = Dag::new("g".into());
+        dag.add_task(
+            TaskNode::new("flaky".to_string(), || Err("busy".to_string())).with_retries(3),
+        );
+        let scheduler = Scheduler::new(dag).with_retry_limiter(Arc::new(limiter));
+
+        assert!(scheduler.execute().is_err());
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn default_task_timeout_applies_unless_overridden() {
+        let sleepy = |ms| {
+            move || {
+                thread::sleep(Duration::from_millis(ms));
+                Ok(())
+            }
+        };
+        let run = |node: TaskNode| {
+            let mut dag = Dag::new("g".into());
+            dag.add_task(node);
+            Scheduler::new(dag)
+                .with_default_task_timeout(Duration::from_millis(50))
+                .execute()
+        };
+
+        assert_eq!(
+            run(TaskNode::new("slow".to_string(), sleepy(300))),
+            Err("Task 'slow' failed: timed out after 50ms".to_string())
+        );
+        assert_eq!(run(TaskNode::new("quick".to_string(), sleepy(0))), Ok(()));
+        assert_eq!(
+            run(TaskNode::new("patient".to_string(), sleepy(150))
+                .with_timeout(Duration::from_secs(5))),
+            Ok(())
+        );
+        assert_eq!(
+            run(TaskNode::new("strict".to_string(), sleepy(300))
+                .with_timeout(Duration::from_millis(20))),
+            Err("Task 'strict' failed: timed out after 20ms".to_string())
+        );
+    }
+
+    #[test]
+    fn running_nodes_tracks_in_flight_tasks() {
+        let mut dag = Dag::new("g".into());
+        for name in ["a", "b", "c"] {
+            dag.add_task(TaskNode::new(name.to_string(), || {
+                thread::sleep(Duration::from_millis(200));
+                Ok(())
+            }));
+        }
+        let scheduler = Scheduler::new(dag);
+        let running = scheduler.running_nodes();
+
+        thread::scope(|scope| {
+            let run = scope.spawn(|| scheduler.execute_parallel(2));
+            thread::sleep(Duration::from_millis(80));
+            let mid_run = running.lock().unwrap().clone();
+            assert_eq!(mid_run.len(), 2, "{:?}", mid_run);
+            assert_eq!(run.join().unwrap(), Ok(()));
+        });
+        assert!(running.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn debug_state_shows_a_partially_run_graph() {
+        let release = Arc::new(AtomicBool::new(false));
+        let held = release.clone();
+        let mut dag = Dag::new("g".into());
+        let start = TaskNode::new("start".to_string(), || Ok(()));
+        let slow = TaskNode::new("slow".to_string(), move || {
+            while !held.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(())
+        });
+        let queued = TaskNode::new("queued".to_string(), || Ok(()));
+        let sink = TaskNode::new("sink".to_string(), || Ok(()));
+        dag.add_task_relation(start.clone(), slow.clone());
+        dag.add_task_relation(start, queued);
+        dag.add_task_relation(slow, sink);
+        let scheduler = Scheduler::new(dag);
+
+        thread::scope(|scope| {
+            let run = scope.spawn(|| scheduler.execute_parallel(1));
+            while !scheduler.debug_state().contains("in flight: slow") {
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert_eq!(
+                scheduler.debug_state(),
+                "in flight: slow\nready: queued\nwaiting: sink (1 upstream left)\n"
+            );
+            release.store(true, Ordering::SeqCst);
+            assert_eq!(run.join().unwrap(), Ok(()));
+        });
+        assert_eq!(
+            scheduler.debug_state(),
+            "in flight: (none)\nready: (none)\n"
+        );
+    }
+
+    #[test]
+    fn teardown_policy_gathers_or_stops_at_first_failure() {
+        let run = |policy| {
+            let mut dag = Dag::new("g".into());
+            dag.add_task(TaskNode::new("main".to_string(), || Ok(())));
+            // Teardown runs in reverse registration order: second, then first
+            dag.add_teardown(TaskNode::new("first".to_string(), || {
+                Err("no lock".to_string())
+            }));
+            dag.add_teardown(TaskNode::new("second".to_string(), || {
+                Err("no disk".to_string())
+            }));
+            let scheduler = Scheduler::new(dag).with_teardown_policy(policy);
+            assert_eq!(scheduler.execute(), Ok(()));
+            scheduler.history().pop().unwrap().report.teardown
+        };
+        let statuses = |teardown: Vec<NodeReport>| {
+            teardown
+                .into_iter()
+                .map(|node| (node.name, node.status))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            statuses(run(TeardownPolicy::Gather)),
+            vec![
+                ("second".to_string(), NodeStatus::Failed("no disk".into())),
+                ("first".to_string(), NodeStatus::Failed("no lock".into())),
+            ]
+        );
+        assert_eq!(
+            statuses(run(TeardownPolicy::FailFast)),
+            vec![
+                ("second".to_string(), NodeStatus::Failed("no disk".into())),
+                (
+                    "first".to_string(),
+                    NodeStatus::Skipped("earlier teardown task failed".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn log_dir_receives_subprocess_output() {
+        use crate::task::ShellTask;
+
+        let dir = std::env::temp_dir().join(format!("seadog-logs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new(
+            "greet".to_string(),
+            ShellTask::new("echo hello; echo oops >&2"),
+        ));
+        let scheduler = Scheduler::new(dag).with_log_dir(&dir);
+
+        assert_eq!(scheduler.execute(), Ok(()));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("greet.log")).unwrap(),
+            "hello\noops\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Output checked by a success predicate is logged and still checked
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new(
+            "check".to_string(),
+            ShellTask::new("echo ERROR: disk full")
+                .success_if(|stdout, _, _| !stdout.contains("ERROR")),
+        ));
+        assert!(Scheduler::new(dag).with_log_dir(&dir).execute().is_err());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("check.log")).unwrap(),
+            "ERROR: disk full\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Path separators in a node name stay inside the log directory
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new(
+            "../escape/a\\b".to_string(),
+            ShellTask::new("echo contained"),
+        ));
+        assert_eq!(Scheduler::new(dag).with_log_dir(&dir).execute(), Ok(()));
+        assert_eq!(
+            std::fs::read_to_string(dir.join(".._escape_a_b.log")).unwrap(),
+            "contained\n"
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // A log that cannot be created fails the task
+        let blocked = std::env::temp_dir().join(format!("seadog-logs-{}.f", std::process::id()));
+        std::fs::write(&blocked, "").unwrap();
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("greet".to_string(), ShellTask::new("true")));
+        let err = Scheduler::new(dag)
+            .with_log_dir(&blocked)
+            .execute()
+            .unwrap_err();
+        assert!(err.contains("could not open log file"), "{}", err);
+        let _ = std::fs::remove_file(&blocked);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancel_token_kills_the_running_task_and_skips_the_rest() {
+        use crate::task::ShellTask;
+
+        let cancel = CancelToken::new();
+        let mut dag = Dag::new("g".into());
+        // Neither the task nor its timeout wrapper is given the token
+        let slow = TaskNode::new("slow".to_string(), ShellTask::new("sleep 10"))
+            .with_timeout(Duration::from_secs(30));
+        dag.add_task_relation(slow, TaskNode::new("after".to_string(), || Ok(())));
+        let scheduler = Scheduler::new(dag).with_cancel_token(cancel.clone());
+
+        let started = Instant::now();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+            });
+            assert!(scheduler.execute().is_err());
+        });
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let report = &scheduler.history()[0].report;
+        let status = |name: &str| {
+            let node = report.nodes.iter().find(|node| node.name == name).unwrap();
+            node.status.clone()
+        };
+        assert_eq!(
+            status("slow"),
+            NodeStatus::Failed("cancelled; process killed".into())
+        );
+        assert!(matches!(status("after"), NodeStatus::Skipped(_)));
+
+        // Later runs do not start anything
+        assert!(scheduler.execute().unwrap_err().contains("run cancelled"));
+    }
+
+    #[test]
+    fn eta_shrinks_as_nodes_finish() {
+        use std::sync::mpsc;
+
+        let mut dag = Dag::new("g".into());
+        let mut release = Vec::new();
+        let mut previous: Option<TaskNode> = None;
+        for (name, millis) in [("a", 100), ("b", 200), ("c", 300)] {
+            let (tx, rx) = mpsc::channel::<()>();
+            release.push(tx);
+            let rx = Mutex::new(rx);
+            let node = TaskNode::new(name.to_string(), move || {
+                rx.lock().unwrap().recv().map_err(|e| e.to_string())
+            })
+            .with_estimated_duration(Duration::from_millis(millis));
+            match previous.replace(node.clone()) {
+                Some(upstream) => dag.add_task_relation(upstream, node),
+                None => dag.add_task(node),
+            }
+        }
+        let scheduler = Scheduler::new(dag);
+        assert_eq!(scheduler.eta(), None);
+
+        let wait_for = |name: &str| {
+            while !scheduler.running_nodes().lock().unwrap().contains(name) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        thread::scope(|scope| {
+            let run = scope.spawn(|| scheduler.execute());
+            let mut etas = Vec::new();
+            for (name, tx) in ["a", "b", "c"].into_iter().zip(&release) {
+                wait_for(name);
+                etas.push(scheduler.eta().unwrap());
+                tx.send(()).unwrap();
+            }
+            assert_eq!(run.join().unwrap(), Ok(()));
+            assert_eq!(
+                etas,
+                vec![
+                    Duration::from_millis(600),
+                    Duration::from_millis(500),
+                    Duration::from_millis(300)
+                ]
+            );
+        });
+        assert_eq!(scheduler.eta(), None);
+    }
+
+    #[test]
+    fn recover_finishes_the_work_left_by_a_crashed_run() {
+        let dir = std::env::temp_dir().join(format!("seadog-queue-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (queue_file, crashed) = (dir.join("queue.json"), dir.join("crashed.json"));
+
+        // extract -> transform -> load; transform snapshots the queue mid-run, which is
+        // what a crash at that point would leave on disk
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let build = |crash: bool| {
+            let node = |name: &str| {
+                let (runs, name) = (runs.clone(), name.to_string());
+                let (queue_file, crashed) = (queue_file.clone(), crashed.clone());
+                TaskNode::new(name.clone(), move || {
+                    runs.lock().unwrap().push(name.clone());
+                    if crash && name == "transform" {
+                        std::fs::copy(&queue_file, &crashed).unwrap();
+                        return Err("crashed".into());
+                    }
+                    Ok(())
+                })
+            };
+            let (extract, transform, load) = (node("extract"), node("transform"), node("load"));
+            let mut dag = Dag::new("etl".into());
+            dag.add_task_relation(extract, transform.clone());
+            dag.add_task_relation(transform, load);
+            dag
+        };
+
+        let scheduler = Scheduler::new(build(true)).with_queue_file(&queue_file);
+        assert!(scheduler.execute().is_err());
+        drop(scheduler);
+        let snapshot = std::fs::read_to_string(&crashed).unwrap();
+        assert!(
+            snapshot.contains("\"in_flight\": [\n    \"transform\""),
+            "{}",
+            snapshot
+        );
+
+        runs.lock().unwrap().clear();
+        let scheduler = Scheduler::new(build(false));
+        assert_eq!(scheduler.recover(&crashed), Ok(()));
+        assert_eq!(*runs.lock().unwrap(), vec!["transform", "load"]);
+
+        // The recovered queue is complete, so recovering again runs nothing
+        runs.lock().unwrap().clear();
+        assert_eq!(scheduler.recover(&crashed), Ok(()));
+        assert!(runs.lock().unwrap().is_empty());
+
+        let other = Scheduler::new(Dag::new("other".into()));
+        assert!(
+            other
+                .recover(&crashed)
+                .unwrap_err()
+                .contains("is for DAG 'etl'")
+        );
+        assert!(other.recover(dir.join("missing.json")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,8 +1,178 @@
-use std::process::Command;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::context::Context;
+use crate::control::{CancelToken, ProcessSlots, RetryLimiter};
+use crate::dag::catch_panic;
+use crate::hash::hmac_sha256_hex;
+
+/// Default cap on captured bytes per output stream (1 MiB).
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Successful result of a task run, with any non-fatal warnings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskOutcome {
+    pub warnings: Vec<String>,
+    /// Name of the one direct successor that should run; see `BranchTask`.
+    pub branch: Option<String>,
+}
+
+/// Result of one run, with the exit status of the subprocess it started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskRun {
+    pub result: Result<TaskOutcome, String>,
+    /// `None` when the run started no subprocess, or it was killed by a signal.
+    pub exit_code: Option<i32>,
+}
+
+impl From<Result<TaskOutcome, String>> for TaskRun {
+    fn from(result: Result<TaskOutcome, String>) -> Self {
+        TaskRun {
+            result,
+            exit_code: None,
+        }
+    }
+}
+
+/// Settings of the run a task is part of, for tasks that start subprocesses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunEnv<'a> {
+    /// File that receives the subprocess's stdout and stderr.
+    pub log: Option<&'a Path>,
+    /// Kills the subprocess once cancelled, like the task's own cancel token.
+    pub cancel: Option<&'a CancelToken>,
+    /// Kills the subprocess once this passes, on top of the task's own timeout.
+    pub deadline: Option<Instant>,
+}
+
+impl TaskOutcome {
+    /// Success with a single warning.
+    pub fn warning<S: Into<String>>(message: S) -> Self {
+        TaskOutcome {
+            warnings: vec![message.into()],
+            ..Default::default()
+        }
+    }
+
+    /// Success that lets only the successor called `name` run.
+    pub fn branch<S: Into<String>>(name: S) -> Self {
+        TaskOutcome {
+            branch: Some(name.into()),
+            ..Default::default()
+        }
+    }
+}
 
 /// Task trait abstraction
 pub trait Task: Send + Sync {
     fn execute(&self) -> Result<(), String>;
+
+    /// Like `execute`, told the id of the node the task runs as, e.g. for logging.
+    ///
+    /// Defaults to `execute`.
+    fn execute_with_id(&self, _node_id: &str) -> Result<(), String> {
+        self.execute()
+    }
+
+    /// Like `execute`, but a success may carry warnings for the execution report.
+    ///
+    /// Defaults to `execute` with no warnings. Tasks that warn override this and
+    /// implement `execute` by discarding the outcome.
+    fn run(&self) -> Result<TaskOutcome, String> {
+        self.execute().map(|()| TaskOutcome::default())
+    }
+
+    /// Like `run`, told the id of the node the task runs as; the executor calls this
+    ///
+    /// Defaults to `execute_with_id` with no warnings, so tasks that override `run`
+    /// should override this too, usually by calling `run`.
+    fn run_with_id(&self, node_id: &str) -> Result<TaskOutcome, String> {
+        self.execute_with_id(node_id)
+            .map(|()| TaskOutcome::default())
+    }
+
+    /// Like `run_with_id`, with the log file, cancel token and deadline of the run; see
+    /// `RunEnv`
+    ///
+    /// Defaults to `run_with_id`, since only subprocess tasks use them. Failing to create
+    /// the log file fails the task.
+    fn run_in(&self, node_id: &str, _env: &RunEnv<'_>) -> Result<TaskOutcome, String> {
+        self.run_with_id(node_id)
+    }
+
+    /// Like `run_in`, also returning the exit status of the subprocess this run started
+    ///
+    /// The executor calls this, so the status it reports belongs to the same run as the
+    /// result even when one task runs several times at once. Defaults to `run_in` with
+    /// no status; subprocess tasks and wrappers override it.
+    fn run_with_status(&self, node_id: &str, env: &RunEnv<'_>) -> TaskRun {
+        self.run_in(node_id, env).into()
+    }
+
+    /// Short name of the task kind, used in reports and exports.
+    fn type_name(&self) -> &'static str {
+        "task"
+    }
+
+    /// Exit status of the subprocess started by the most recent run
+    ///
+    /// `None` for tasks that do not run a subprocess, and when the process was killed
+    /// by a signal or never started. When the task runs several times at once, this is
+    /// whichever run finished last; `run_with_status` returns the status of one run.
+    fn exit_code(&self) -> Option<i32> {
+        None
+    }
+
+    /// Identity of the work the task does, for running identical tasks only once
+    ///
+    /// Two tasks with the same fingerprint must be interchangeable: running either one
+    /// has the same effect and outcome. `None`, the default, never deduplicates.
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    /// Parameters needed to recreate the task when a DAG is exported.
+    ///
+    /// Tasks that cannot be serialized, like closures, return an empty map.
+    fn params(&self) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+
+    /// Whether the program the task would start can be found, checked without running
+    /// anything, e.g. for a dry run
+    ///
+    /// Defaults to `true`, since only subprocess tasks start a program.
+    fn executable_found(&self) -> bool {
+        true
+    }
+
+    /// Whether `run_in` returns by itself soon after `RunEnv::deadline` passes, so
+    /// `TimeoutTask` can run it directly instead of on a helper thread it abandons
+    ///
+    /// Defaults to `false`. Subprocess tasks return `true`, since they kill their
+    /// process at the deadline.
+    fn stops_at_deadline(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the task a wrapper holds, passing the node id and run settings on when the
+/// wrapper has them.
+fn run_inner(task: &dyn Task, node_id: Option<&str>, env: &RunEnv<'_>) -> TaskRun {
+    match node_id {
+        Some(node_id) => task.run_with_status(node_id, env),
+        None => task.run().into(),
+    }
 }
 
 /// Blanket implementation so existing fn() -> Result<(), String> still works.
@@ -13,40 +183,1056 @@ where
     fn execute(&self) -> Result<(), String> {
         (self)()
     }
+
+    fn type_name(&self) -> &'static str {
+        "closure"
+    }
+}
+
+/// Closure task with access to a shared `Context`
+pub struct FnContextTask<F> {
+    context: Context,
+    func: F,
+}
+
+impl<F> FnContextTask<F>
+where
+    F: Fn(&Context) -> Result<(), String> + Send + Sync,
+{
+    pub fn new(context: Context, func: F) -> Self {
+        FnContextTask { context, func }
+    }
+}
+
+impl<F> Task for FnContextTask<F>
+where
+    F: Fn(&Context) -> Result<(), String> + Send + Sync,
+{
+    fn type_name(&self) -> &'static str {
+        "closure"
+    }
+
+    fn execute(&self) -> Result<(), String> {
+        (self.func)(&self.context)
+    }
+}
+
+/// Closure task that can report warnings alongside success
+pub struct FnOutcomeTask<F>(F);
+
+impl<F> FnOutcomeTask<F>
+where
+    F: Fn() -> Result<TaskOutcome, String> + Send + Sync,
+{
+    pub fn new(func: F) -> Self {
+        FnOutcomeTask(func)
+    }
+}
+
+impl<F> Task for FnOutcomeTask<F>
+where
+    F: Fn() -> Result<TaskOutcome, String> + Send + Sync,
+{
+    fn type_name(&self) -> &'static str {
+        "closure"
+    }
+
+    fn execute(&self) -> Result<(), String> {
+        (self.0)().map(drop)
+    }
+
+    fn run(&self) -> Result<TaskOutcome, String> {
+        (self.0)()
+    }
+
+    fn run_with_id(&self, _node_id: &str) -> Result<TaskOutcome, String> {
+        self.run()
+    }
+}
+
+/// Task that chooses which downstream path runs
+///
+/// The selector returns the name of one of the node's direct successors. That successor
+/// runs; the others are skipped, together with every downstream node that can only be
+/// reached through them. Naming a node that is not a direct successor fails the task.
+pub struct BranchTask<F>(F);
+
+impl<F> BranchTask<F>
+where
+    F: Fn() -> Result<String, String> + Send + Sync,
+{
+    pub fn new(selector: F) -> Self {
+        BranchTask(selector)
+    }
+}
+
+impl<F> Task for BranchTask<F>
+where
+    F: Fn() -> Result<String, String> + Send + Sync,
+{
+    fn type_name(&self) -> &'static str {
+        "branch"
+    }
+
+    fn execute(&self) -> Result<(), String> {
+        (self.0)().map(drop)
+    }
+
+    fn run(&self) -> Result<TaskOutcome, String> {
+        (self.0)().map(TaskOutcome::branch)
+    }
+
+    fn run_with_id(&self, _node_id: &str) -> Result<TaskOutcome, String> {
+        self.run()
+    }
+}
+
+/// Retry wrapper with exponential backoff
+///
+/// Runs the inner task up to `retries + 1` times. Before retry `n` (counting from 1)
+/// it waits `backoff * 2^(n-1)` on its clock. With a maximum duration, it also stops
+/// retrying once the next attempt would start after that much time has passed.
+pub struct RetryTask {
+    task: Arc<dyn Task>,
+    retries: u32,
+    backoff: Duration,
+    max_duration: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    limiter: Option<Arc<RetryLimiter>>,
+}
+
+impl RetryTask {
+    pub fn new<T: Task + 'static>(task: T, retries: u32) -> Self {
+        RetryTask {
+            task: Arc::new(task),
+            retries,
+            backoff: Duration::ZERO,
+            max_duration: None,
+            clock: Arc::new(SystemClock),
+            limiter: None,
+        }
+    }
+
+    /// Retries for as long as `max_duration` allows, however many attempts that takes.
+    pub fn for_duration<T: Task + 'static>(task: T, max_duration: Duration) -> Self {
+        RetryTask::new(task, u32::MAX).with_max_duration(max_duration)
+    }
+
+    /// Gives up, returning the last error, once a retry would start more than
+    /// `max_duration` after the first attempt.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Sets the wait before the first retry; each further retry doubles it.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Takes a token from `limiter` before every retry, after the backoff wait.
+    ///
+    /// Share one limiter between tasks that call the same rate-limited service.
+    pub fn with_limiter(mut self, limiter: Arc<RetryLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+}
+
+impl Task for RetryTask {
+    fn type_name(&self) -> &'static str {
+        self.task.type_name()
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.task
+            .fingerprint()
+            .map(|inner| format!("retry({:?}):{}", (self.retries, self.max_duration), inner))
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.task.exit_code()
+    }
+
+    fn params(&self) -> BTreeMap<String, String> {
+        self.task.params()
+    }
+
+    fn executable_found(&self) -> bool {
+        self.task.executable_found()
+    }
+
+    fn execute(&self) -> Result<(), String> {
+        self.run().map(drop)
+    }
+
+    fn execute_with_id(&self, node_id: &str) -> Result<(), String> {
+        self.run_with_id(node_id).map(drop)
+    }
+
+    fn run(&self) -> Result<TaskOutcome, String> {
+        self.run_attempts(None, &RunEnv::default()).result
+    }
+
+    fn run_with_id(&self, node_id: &str) -> Result<TaskOutcome, String> {
+        self.run_in(node_id, &RunEnv::default())
+    }
+
+    fn run_in(&self, node_id: &str, env: &RunEnv<'_>) -> Result<TaskOutcome, String> {
+        self.run_with_status(node_id, env).result
+    }
+
+    fn run_with_status(&self, node_id: &str, env: &RunEnv<'_>) -> TaskRun {
+        self.run_attempts(Some(node_id), env)
+    }
+}
+
+impl RetryTask {
+    fn run_attempts(&self, node_id: Option<&str>, env: &RunEnv<'_>) -> TaskRun {
+        let deadline = self
+            .max_duration
+            .and_then(|max| self.clock.now().checked_add(max));
+        let mut retry = 0;
+        loop {
+            let delay = self.backoff.saturating_mul(2u32.saturating_pow(retry));
+            let expired = || {
+                deadline.is_some_and(|deadline| {
+                    self.clock
+                        .now()
+                        .checked_add(delay)
+                        .is_none_or(|start| start > deadline)
+                })
+            };
+            let run = run_inner(&*self.task, node_id, env);
+            if run.result.is_ok() || retry >= self.retries || expired() {
+                return run;
+            }
+            retry += 1;
+            self.clock.sleep(delay);
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire();
+            }
+        }
+    }
+}
+
+/// Timeout wrapper
+///
+/// Fails once `timeout` has passed. The deadline is passed on in `RunEnv`, so a
+/// subprocess task inside, even one wrapped in a `RetryTask`, kills its process then.
+/// Tasks that stop at the deadline by themselves, see `Task::stops_at_deadline`, run
+/// directly. Others run on a helper thread that is waited for on the wrapper's clock; a
+/// timed-out task cannot be stopped, so that thread is left to finish in the background.
+pub struct TimeoutTask {
+    task: Arc<dyn Task>,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+/// How often `TimeoutTask` checks whether its inner task has finished.
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+impl TimeoutTask {
+    pub fn new<T: Task + 'static>(task: T, timeout: Duration) -> Self {
+        Self::shared(Arc::new(task), timeout)
+    }
+
+    /// Wraps a task that is already shared, such as a node's.
+    pub fn shared(task: Arc<dyn Task>, timeout: Duration) -> Self {
+        TimeoutTask {
+            task,
+            timeout,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+impl Task for TimeoutTask {
+    fn type_name(&self) -> &'static str {
+        self.task.type_name()
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.task
+            .fingerprint()
+            .map(|inner| format!("timeout({:?}):{}", self.timeout, inner))
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.task.exit_code()
+    }
+
+    fn params(&self) -> BTreeMap<String, String> {
+        self.task.params()
+    }
+
+    fn executable_found(&self) -> bool {
+        self.task.executable_found()
+    }
+
+    fn execute(&self) -> Result<(), String> {
+        self.run().map(drop)
+    }
+
+    fn execute_with_id(&self, node_id: &str) -> Result<(), String> {
+        self.run_with_id(node_id).map(drop)
+    }
+
+    fn run(&self) -> Result<TaskOutcome, String> {
+        self.run_bounded(None, &RunEnv::default()).result
+    }
+
+    fn run_with_id(&self, node_id: &str) -> Result<TaskOutcome, String> {
+        self.run_in(node_id, &RunEnv::default())
+    }
+
+    fn run_in(&self, node_id: &str, env: &RunEnv<'_>) -> Result<TaskOutcome, String> {
+        self.run_with_status(node_id, env).result
+    }
+
+    fn run_with_status(&self, node_id: &str, env: &RunEnv<'_>) -> TaskRun {
+        self.run_bounded(Some(node_id), env)
+    }
+
+    fn stops_at_deadline(&self) -> bool {
+        true
+    }
+}
+
+impl TimeoutTask {
+    fn run_bounded(&self, node_id: Option<&str>, env: &RunEnv<'_>) -> TaskRun {
+        let ours = Instant::now() + self.timeout;
+        let env = RunEnv {
+            deadline: Some(env.deadline.map_or(ours, |theirs| theirs.min(ours))),
+            ..*env
+        };
+        // Without a node id the task runs through `run`, which never sees the deadline
+        if node_id.is_some() && self.task.stops_at_deadline() {
+            return catch_panic(|| Ok(run_inner(&*self.task, node_id, &env)))
+                .unwrap_or_else(|err| Err(err).into());
+        }
+        let (tx, rx) = mpsc::channel();
+        let deadline = env.deadline;
+        let task = self.task.clone();
+        let node_id = node_id.map(str::to_string);
+        let log = env.log.map(Path::to_path_buf);
+        let cancel = env.cancel.cloned();
+        thread::spawn(move || {
+            let env = RunEnv {
+                log: log.as_deref(),
+                cancel: cancel.as_ref(),
+                deadline,
+            };
+            let run = catch_panic(|| Ok(run_inner(&*task, node_id.as_deref(), &env)));
+            let _ = tx.send(run.unwrap_or_else(|err| Err(err).into()));
+        });
+
+        let deadline = self.clock.now() + self.timeout;
+        loop {
+            match rx.try_recv() {
+                Ok(run) => return run,
+                Err(TryRecvError::Disconnected) => return Err("task panicked".into()).into(),
+                Err(TryRecvError::Empty) if self.clock.now() >= deadline => {
+                    return Err(format!("timed out after {:?}", self.timeout)).into();
+                }
+                Err(TryRecvError::Empty) => self.clock.sleep(TIMEOUT_POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+/// Output captured from a subprocess task's last run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Decides from stdout, stderr and the exit code whether a command succeeded; see
+/// `ShellTask::success_if`.
+pub type SuccessPredicate = fn(stdout: &str, stderr: &str, code: i32) -> bool;
+
+/// What a subprocess task remembers about its most recent run.
+#[derive(Debug, Default)]
+struct LastRun {
+    output: Option<CapturedOutput>,
+    exit_code: Option<i32>,
+}
+
+/// How often a running subprocess is polled for exit.
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Opens `path` for appending, creating it and its directory if needed.
+fn open_log(path: &Path) -> Result<File, String> {
+    let failed = |e: io::Error| format!("could not open log file {}: {}", path.display(), e);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(failed)
+}
+
+/// Spawns `command` and waits for it, killing it once `deadline` passes
+///
+/// With `capture` set to a byte cap, stdout/stderr are piped and at most that many bytes
+/// of each are kept; output past the cap is still drained, so the child never blocks on
+/// a full pipe. Everything read is also written to `tee`, if given, in full. Otherwise
+/// the child keeps the stdio already set on `command`, which defaults to ours.
+///
+/// The child is also killed as soon as any of the `cancel` tokens is cancelled. On Unix the child leads a
+/// process group of its own and the whole group is killed, so processes it spawned in
+/// the background die with it instead of holding the output pipes open.
+fn run_process(
+    command: &mut Command,
+    capture: Option<usize>,
+    tee: Option<&File>,
+    deadline: Option<Instant>,
+    cancel: &[Option<&CancelToken>],
+) -> Result<(ExitStatus, Option<CapturedOutput>), String> {
+    if capture.is_some() {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Command failed: {}", e))?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let max_bytes = capture.unwrap_or(0);
+
+    thread::scope(|scope| {
+        let stdout = stdout.map(|out| scope.spawn(move || read_capped(out, max_bytes, tee)));
+        let stderr = stderr.map(|err| scope.spawn(move || read_capped(err, max_bytes, tee)));
+
+        let status = wait_with_deadline(&mut child, deadline, cancel)?;
+
+        let collect = |reader: Option<thread::ScopedJoinHandle<'_, io::Result<String>>>| {
+            reader
+                .map(|handle| handle.join().expect("output reader panicked"))
+                .transpose()
+                .map_err(|e| format!("Command failed: {}", e))
+        };
+        let stdout = collect(stdout)?;
+        let stderr = collect(stderr)?;
+        let output = capture.map(|_| CapturedOutput {
+            stdout: stdout.unwrap_or_default(),
+            stderr: stderr.unwrap_or_default(),
+        });
+        Ok((status, output))
+    })
+}
+
+/// One subprocess invocation, as configured on a subprocess task.
+struct CommandSpec<'a> {
+    program: &'a str,
+    args: &'a [&'a str],
+    /// Extra environment variables on top of the inherited environment.
+    env: &'a [(String, String)],
+    /// Like `env`, for values that must not be shown.
+    secret_env: &'a HashMap<String, String>,
+    cwd: Option<&'a str>,
+    timeout: Option<Duration>,
+    /// Byte cap per stream when capturing output.
+    capture: Option<usize>,
+    /// Non-zero exit codes that also count as success.
+    accepted_exit_codes: &'a [i32],
+    /// Slot to hold while the process runs.
+    slots: Option<&'a ProcessSlots>,
+    /// File that receives stdout and stderr, besides any capture.
+    log: Option<&'a Path>,
+    /// Kill the process once any of them is cancelled.
+    cancel: &'a [Option<&'a CancelToken>],
+    /// Kill the process once this passes, even before `timeout` does.
+    deadline: Option<Instant>,
+    /// Replaces the exit code check.
+    success_if: Option<SuccessPredicate>,
+}
+
+/// Runs `spec` to completion, returning its exit code with the result and storing it
+/// and the captured output in `last_run` too.
+fn run_command(spec: &CommandSpec<'_>, last_run: &Mutex<LastRun>) -> TaskRun {
+    last_run.lock().unwrap().exit_code = None;
+    let (status, output) = match start_command(spec) {
+        Ok(finished) => finished,
+        Err(err) => return Err(err).into(),
+    };
+    let verdict = spec.success_if.map(|succeeded| {
+        let (stdout, stderr) = output
+            .as_ref()
+            .map_or(("", ""), |output| (&output.stdout[..], &output.stderr[..]));
+        status
+            .code()
+            .is_some_and(|code| succeeded(stdout, stderr, code))
+    });
+    let mut last = last_run.lock().unwrap();
+    last.exit_code = status.code();
+    if output.is_some() {
+        last.output = output;
+    }
+    drop(last);
+    let accepted = status
+        .code()
+        .is_some_and(|code| spec.accepted_exit_codes.contains(&code));
+    let result = match verdict {
+        Some(true) => Ok(TaskOutcome::default()),
+        Some(false) => Err(format!(
+            "Command failed its success check with status: {}",
+            status
+        )),
+        None if status.success() || accepted => Ok(TaskOutcome::default()),
+        None => Err(format!("Command failed with status: {}", status)),
+    };
+    TaskRun {
+        result,
+        exit_code: status.code(),
+    }
+}
+
+/// Spawns the process `spec` describes, holding a slot while it runs, and waits for it.
+fn start_command(spec: &CommandSpec<'_>) -> Result<(ExitStatus, Option<CapturedOutput>), String> {
+    let _permit = spec.slots.map(ProcessSlots::acquire).transpose()?;
+    if any_cancelled(spec.cancel) {
+        return Err("cancelled".into());
+    }
+    if spec
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+    {
+        return Err("timed out".into());
+    }
+    let mut command = Command::new(spec.program);
+    command.args(spec.args);
+    command.envs(spec.env.iter().map(|(key, value)| (key, value)));
+    command.envs(spec.secret_env);
+    if let Some(cwd) = spec.cwd {
+        command.current_dir(cwd);
+    }
+    // Captured output is copied to the log as it is read, so both see all of it
+    let mut tee = None;
+    if let Some(log) = spec.log {
+        let file = open_log(log)?;
+        if spec.capture.is_some() {
+            tee = Some(file);
+        } else {
+            let stderr = file
+                .try_clone()
+                .map_err(|e| format!("Command failed: {}", e))?;
+            command.stdin(Stdio::null()).stdout(file).stderr(stderr);
+        }
+    }
+
+    // The task's own timeout counts from the spawn, after any wait for a slot
+    let timeout = spec.timeout.map(|timeout| Instant::now() + timeout);
+    let deadline = match (timeout, spec.deadline) {
+        (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+        (timeout, deadline) => timeout.or(deadline),
+    };
+    run_process(
+        &mut command,
+        spec.capture,
+        tee.as_ref(),
+        deadline,
+        spec.cancel,
+    )
+}
+
+/// Placeholder shown instead of a secret value.
+const REDACTED: &str = "***";
+
+/// Adds the settings shell and python tasks share to exported `params`
+///
+/// Each variable in `env` becomes an `env.NAME` key. Secret variables are never
+/// exported, and neither are concurrency slots, cancel tokens or success predicates.
+fn insert_process_params(
+    params: &mut BTreeMap<String, String>,
+    capture_output: bool,
+    max_output_bytes: usize,
+    timeout: Option<Duration>,
+    env: &[(String, String)],
+) {
+    if capture_output {
+        params.insert("capture_output".to_string(), "true".to_string());
+    }
+    if max_output_bytes != DEFAULT_MAX_OUTPUT_BYTES {
+        params.insert("max_output_bytes".to_string(), max_output_bytes.to_string());
+    }
+    if let Some(timeout) = timeout {
+        params.insert("timeout_ms".to_string(), timeout.as_millis().to_string());
+    }
+    for (key, value) in env {
+        params.insert(format!("env.{}", key), value.clone());
+    }
+}
+
+/// Secret keys mapped to `REDACTED`, sorted, for `Debug` output.
+fn redacted(secret_env: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    secret_env
+        .keys()
+        .map(|key| (key.as_str(), REDACTED))
+        .collect()
+}
+
+/// Secret keys with an HMAC of each value, so fingerprints tell secrets apart
+/// without containing them
+///
+/// The HMAC key is random and fresh in every process, so a digest seen in a log cannot
+/// be checked against guessed values elsewhere, and fingerprints of tasks with secrets
+/// only match within one process.
+fn secret_digests(secret_env: &HashMap<String, String>) -> BTreeMap<&str, String> {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    let key = KEY.get_or_init(|| {
+        let mut key = [0u8; 32];
+        key[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        key
+    });
+    secret_env
+        .iter()
+        .map(|(key_name, value)| (key_name.as_str(), hmac_sha256_hex(key, value.as_bytes())))
+        .collect()
+}
+
+/// Words at the start of a shell command that name no program on `PATH`.
+const SHELL_BUILTINS: [&str; 26] = [
+    ".", ":", "[", "alias", "case", "cd", "command", "eval", "exec", "exit", "export", "for", "if",
+    "read", "return", "set", "shift", "source", "test", "trap", "type", "ulimit", "umask", "unset",
+    "until", "while",
+];
+
+/// The program `command` starts with, if it is a plain word worth looking up.
+fn shell_program(command: &str) -> Option<&str> {
+    let word = command.split_whitespace().find(|word| {
+        !word
+            .split_once('=')
+            .is_some_and(|(name, _)| is_variable_name(name))
+    })?;
+    let plain = !word.contains([
+        '$', '`', '\'', '"', '(', ')', '{', '}', ';', '&', '|', '<', '>',
+    ]);
+    (plain && !SHELL_BUILTINS.contains(&word)).then_some(word)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Resolves `program` the way a shell running in `dir` would: paths are checked as
+/// given, relative ones against `dir` when set, and bare names are looked up in each
+/// `PATH` directory.
+pub(crate) fn find_executable(program: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata().is_ok_and(|meta| {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                meta.is_file() && meta.permissions().mode() & 0o111 != 0
+            }
+            #[cfg(not(unix))]
+            {
+                meta.is_file()
+            }
+        })
+    };
+
+    if program.chars().any(std::path::is_separator) {
+        let path = match dir {
+            Some(dir) => dir.join(program),
+            None => PathBuf::from(program),
+        };
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+fn wait_with_deadline(
+    child: &mut Child,
+    deadline: Option<Instant>,
+    cancel: &[Option<&CancelToken>],
+) -> Result<ExitStatus, String> {
+    // Reap the child so it does not linger as a zombie
+    let kill = |child: &mut Child| {
+        #[cfg(unix)]
+        if let Ok(group) = libc::pid_t::try_from(child.id()) {
+            // SAFETY: `kill` only sends a signal. The child is not reaped yet, so its
+            // pid, which is also its process group id, cannot have been reused.
+            unsafe {
+                libc::kill(-group, libc::SIGKILL);
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    };
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .map_err(|e| format!("Command failed: {}", e))?
+        {
+            return Ok(status);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill(child);
+            return Err("timed out; process killed".into());
+        }
+        if any_cancelled(cancel) {
+            kill(child);
+            return Err("cancelled; process killed".into());
+        }
+        thread::sleep(PROCESS_POLL_INTERVAL);
+    }
+}
+
+fn any_cancelled(tokens: &[Option<&CancelToken>]) -> bool {
+    tokens.iter().flatten().any(|token| token.is_cancelled())
+}
+
+fn read_capped(
+    mut reader: impl Read,
+    max_bytes: usize,
+    mut tee: Option<&File>,
+) -> io::Result<String> {
+    let mut kept = Vec::new();
+    let mut total = 0;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if let Some(file) = &mut tee {
+            file.write_all(&buf[..n])?;
+        }
+        let room = max_bytes.saturating_sub(kept.len());
+        kept.extend_from_slice(&buf[..n.min(room)]);
+        total += n;
+    }
+
+    let mut text = String::from_utf8_lossy(&kept).into_owned();
+    if total > max_bytes {
+        text.push_str(&format!(
+            "\n[output truncated: {} of {} bytes kept]",
+            max_bytes, total
+        ));
+    }
+    Ok(text)
 }
 
 /// Shell task implementation
 pub struct ShellTask {
     pub command: String,
+    /// Non-zero exit codes that also count as success.
+    pub accepted_exit_codes: Vec<i32>,
+    /// Capture stdout/stderr instead of inheriting them; see `last_output`.
+    pub capture_output: bool,
+    /// Cap on captured bytes per stream.
+    pub max_output_bytes: usize,
+    /// Kill the process if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Working directory for the command; inherited from the scheduler when unset.
+    pub cwd: Option<String>,
+    /// Extra environment variables for the command.
+    pub env: Vec<(String, String)>,
+    /// Environment variables whose values are shown as `***` in `Debug` output and
+    /// left out of exports.
+    pub secret_env: HashMap<String, String>,
+    /// Source for `${key}` placeholders in `command` and `cwd`.
+    pub context: Option<Context>,
+    /// Concurrency limit shared with other subprocess tasks.
+    pub slots: Option<Arc<ProcessSlots>>,
+    /// Kills the running process when cancelled.
+    pub cancel: Option<CancelToken>,
+    /// Decides success instead of the exit code; see `success_if`.
+    pub success_if: Option<SuccessPredicate>,
+    last_run: Mutex<LastRun>,
 }
 
 impl ShellTask {
     pub fn new<S: Into<String>>(command: S) -> Self {
         ShellTask {
             command: command.into(),
+            accepted_exit_codes: Vec::new(),
+            capture_output: false,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            timeout: None,
+            cwd: None,
+            env: Vec::new(),
+            secret_env: HashMap::new(),
+            context: None,
+            slots: None,
+            cancel: None,
+            success_if: None,
+            last_run: Mutex::new(LastRun::default()),
         }
     }
+
+    /// Captures stdout/stderr instead of inheriting them.
+    pub fn capture_output(mut self) -> Self {
+        self.capture_output = true;
+        self
+    }
+
+    /// Lets `predicate` decide from the output and exit code whether the command
+    /// succeeded, e.g. to fail a command that exits 0 after printing an error
+    ///
+    /// Output is captured for the predicate, and `accept_exit_codes` no longer applies.
+    /// A process killed by a signal always fails. With `Scheduler::with_log_dir`, the
+    /// output is written to the log file and still reaches the predicate.
+    pub fn success_if(mut self, predicate: SuccessPredicate) -> Self {
+        self.success_if = Some(predicate);
+        self
+    }
+
+    /// Runs the command in `dir`.
+    pub fn in_dir<S: Into<String>>(mut self, dir: S) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Sets an environment variable for the process, on top of the inherited ones.
+    pub fn with_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Like `with_env`, for a value such as a token that must never be displayed.
+    pub fn with_secret_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.secret_env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Fills `${key}` placeholders in the command and working directory from `context`
+    /// just before each run. A placeholder whose key is not set fails the task.
+    ///
+    /// Without a context the command is passed to the shell untouched, so `${VAR}`
+    /// still expands as an environment variable.
+    pub fn with_context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Takes a slot from `slots` before starting the process and holds it until exit.
+    pub fn with_slots(mut self, slots: Arc<ProcessSlots>) -> Self {
+        self.slots = Some(slots);
+        self
+    }
+
+    /// Kills the process and fails the task once `cancel` is cancelled.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Caps captured output per stream; defaults to `DEFAULT_MAX_OUTPUT_BYTES`.
+    pub fn with_max_output_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_output_bytes = max_bytes;
+        self
+    }
+
+    /// Kills the process and fails the task if it runs longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Output of the most recent run, if output capture is enabled.
+    pub fn last_output(&self) -> Option<CapturedOutput> {
+        self.last_run.lock().unwrap().output.clone()
+    }
+
+    /// Treats the given exit codes as success in addition to 0, e.g. `grep`'s 1 for "no match".
+    pub fn accept_exit_codes(mut self, codes: Vec<i32>) -> Self {
+        self.accepted_exit_codes = codes;
+        self
+    }
+}
+
+impl fmt::Debug for ShellTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShellTask")
+            .field("command", &self.command)
+            .field("cwd", &self.cwd)
+            .field("env", &self.env)
+            .field("secret_env", &redacted(&self.secret_env))
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Task for ShellTask {
+    fn type_name(&self) -> &'static str {
+        "shell"
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.last_run.lock().unwrap().exit_code
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        // A context can render the same command differently from run to run
+        if self.context.is_some() {
+            return None;
+        }
+        Some(format!(
+            "shell:{:?}",
+            (
+                &self.command,
+                &self.cwd,
+                &self.env,
+                secret_digests(&self.secret_env),
+                &self.accepted_exit_codes,
+                self.timeout,
+                self.success_if.map(|predicate| predicate as usize)
+            )
+        ))
+    }
+
+    fn params(&self) -> BTreeMap<String, String> {
+        let mut params = BTreeMap::from([("command".to_string(), self.command.clone())]);
+        if let Some(cwd) = &self.cwd {
+            params.insert("cwd".to_string(), cwd.clone());
+        }
+        if !self.accepted_exit_codes.is_empty() {
+            let codes: Vec<String> = self
+                .accepted_exit_codes
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            params.insert("accept_exit_codes".to_string(), codes.join(","));
+        }
+        insert_process_params(
+            &mut params,
+            self.capture_output,
+            self.max_output_bytes,
+            self.timeout,
+            &self.env,
+        );
+        params
+    }
+
+    /// Whether `/bin/sh` and the command's first program can be found
+    ///
+    /// Only the first word of the command is checked, after any `NAME=value`
+    /// assignments. Shell builtins and keywords, and words with quotes or
+    /// substitutions, are not looked up.
+    fn executable_found(&self) -> bool {
+        // A template that does not render fails the run, but not for a missing program
+        let render = |template: &str| self.render(template).unwrap_or_else(|_| template.into());
+        let script = render(&self.command);
+        let cwd = self.cwd.as_deref().map(render);
+        find_executable("/bin/sh", None).is_some()
+            && shell_program(&script).is_none_or(|program| {
+                find_executable(program, cwd.as_deref().map(Path::new)).is_some()
+            })
+    }
+
+    fn stops_at_deadline(&self) -> bool {
+        true
+    }
+
     fn execute(&self) -> Result<(), String> {
-        let status = Command::new("/bin/sh")
-            .arg("-c")
-            .arg(&self.command)
-            .status()
-            .map_err(|e| format!("Command failed: {}", e))?;
-        if status.success() {
-            Ok(())
-        } else {
-            Err(format!("Command failed with status: {}", status))
+        self.run_script(&RunEnv::default()).result.map(drop)
+    }
+
+    fn run_in(&self, _node_id: &str, env: &RunEnv<'_>) -> Result<TaskOutcome, String> {
+        self.run_script(env).result
+    }
+
+    fn run_with_status(&self, _node_id: &str, env: &RunEnv<'_>) -> TaskRun {
+        self.run_script(env)
+    }
+}
+
+impl ShellTask {
+    fn render(&self, template: &str) -> Result<String, String> {
+        match &self.context {
+            Some(context) => context.render(template),
+            None => Ok(template.to_string()),
         }
     }
+
+    fn run_script(&self, env: &RunEnv<'_>) -> TaskRun {
+        let rendered = self.render(&self.command).and_then(|script| {
+            let cwd = self
+                .cwd
+                .as_deref()
+                .map(|cwd| self.render(cwd))
+                .transpose()?;
+            Ok((script, cwd))
+        });
+        let (script, cwd) = match rendered {
+            Ok(rendered) => rendered,
+            Err(err) => return Err(err).into(),
+        };
+        let spec = CommandSpec {
+            program: "/bin/sh",
+            args: &["-c", &script],
+            env: &self.env,
+            secret_env: &self.secret_env,
+            cwd: cwd.as_deref(),
+            timeout: self.timeout,
+            capture: (self.capture_output || self.success_if.is_some())
+                .then_some(self.max_output_bytes),
+            accepted_exit_codes: &self.accepted_exit_codes,
+            slots: self.slots.as_deref(),
+            log: env.log,
+            cancel: &[self.cancel.as_ref(), env.cancel],
+            deadline: env.deadline,
+            success_if: self.success_if,
+        };
+        run_command(&spec, &self.last_run)
+    }
 }
 
+/// Interpreters tried, in order, by a `PythonTask` with fallback enabled.
+pub const PYTHON_FALLBACKS: [&str; 2] = ["python3", "python"];
+
 /// Python task implementation
 pub struct PythonTask {
     pub code: String,
     pub interpreter: String,
+    /// Capture stdout/stderr instead of inheriting them; see `last_output`.
+    pub capture_output: bool,
+    /// Cap on captured bytes per stream.
+    pub max_output_bytes: usize,
+    /// Kill the process if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Extra environment variables for the interpreter.
+    pub env: Vec<(String, String)>,
+    /// Environment variables whose values are shown as `***` in `Debug` output and
+    /// left out of exports.
+    pub secret_env: HashMap<String, String>,
+    /// Concurrency limit shared with other subprocess tasks.
+    pub slots: Option<Arc<ProcessSlots>>,
+    /// Kills the running process when cancelled.
+    pub cancel: Option<CancelToken>,
+    /// Try `PYTHON_FALLBACKS` when `interpreter` is not found.
+    pub fallback: bool,
+    interpreter_used: Mutex<Option<String>>,
+    last_run: Mutex<LastRun>,
 }
 
 impl PythonTask {
@@ -54,29 +1240,194 @@ impl PythonTask {
         PythonTask {
             code: code.into(),
             interpreter: "python3".into(),
+            capture_output: false,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            timeout: None,
+            env: Vec::new(),
+            secret_env: HashMap::new(),
+            slots: None,
+            cancel: None,
+            fallback: false,
+            interpreter_used: Mutex::new(None),
+            last_run: Mutex::new(LastRun::default()),
         }
     }
 
     pub fn with_interpreter<C: Into<String>, I: Into<String>>(code: C, interpreter: I) -> Self {
         PythonTask {
-            code: code.into(),
             interpreter: interpreter.into(),
+            ..PythonTask::new(code)
         }
     }
+
+    /// Captures stdout/stderr instead of inheriting them.
+    pub fn capture_output(mut self) -> Self {
+        self.capture_output = true;
+        self
+    }
+
+    /// Sets an environment variable for the process, on top of the inherited ones.
+    pub fn with_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Like `with_env`, for a value such as a token that must never be displayed.
+    pub fn with_secret_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.secret_env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Takes a slot from `slots` before starting the process and holds it until exit.
+    pub fn with_slots(mut self, slots: Arc<ProcessSlots>) -> Self {
+        self.slots = Some(slots);
+        self
+    }
+
+    /// Kills the process and fails the task once `cancel` is cancelled.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Falls back to the first of `PYTHON_FALLBACKS` found on `PATH` when the configured
+    /// interpreter is missing; see `interpreter_used`.
+    pub fn with_interpreter_fallback(mut self) -> Self {
+        self.fallback = true;
+        self
+    }
+
+    /// Caps captured output per stream; defaults to `DEFAULT_MAX_OUTPUT_BYTES`.
+    pub fn with_max_output_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_output_bytes = max_bytes;
+        self
+    }
+
+    /// Kills the process and fails the task if it runs longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Output of the most recent run, if output capture is enabled.
+    pub fn last_output(&self) -> Option<CapturedOutput> {
+        self.last_run.lock().unwrap().output.clone()
+    }
+
+    /// Interpreter the most recent run started, which differs from `interpreter` after
+    /// a fallback.
+    pub fn interpreter_used(&self) -> Option<String> {
+        self.interpreter_used.lock().unwrap().clone()
+    }
+
+    /// The configured interpreter if it is on `PATH`, else the first available fallback.
+    fn resolve_interpreter(&self) -> Result<&str, String> {
+        let fallbacks: &[&str] = if self.fallback {
+            &PYTHON_FALLBACKS
+        } else {
+            &[]
+        };
+        std::iter::once(self.interpreter.as_str())
+            .chain(fallbacks.iter().copied())
+            .find(|candidate| find_executable(candidate, None).is_some())
+            .ok_or_else(|| format!("interpreter '{}' not found on PATH", self.interpreter))
+    }
+}
+
+impl fmt::Debug for PythonTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PythonTask")
+            .field("code", &self.code)
+            .field("interpreter", &self.interpreter)
+            .field("env", &self.env)
+            .field("secret_env", &redacted(&self.secret_env))
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Task for PythonTask {
+    fn type_name(&self) -> &'static str {
+        "python"
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.last_run.lock().unwrap().exit_code
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!(
+            "python:{:?}",
+            (
+                &self.interpreter,
+                &self.code,
+                &self.env,
+                secret_digests(&self.secret_env),
+                self.timeout
+            )
+        ))
+    }
+
+    fn params(&self) -> BTreeMap<String, String> {
+        let mut params = BTreeMap::from([
+            ("code".to_string(), self.code.clone()),
+            ("interpreter".to_string(), self.interpreter.clone()),
+        ]);
+        insert_process_params(
+            &mut params,
+            self.capture_output,
+            self.max_output_bytes,
+            self.timeout,
+            &self.env,
+        );
+        params
+    }
+
+    /// Whether the interpreter, or with fallback one of `PYTHON_FALLBACKS`, is on `PATH`.
+    fn executable_found(&self) -> bool {
+        self.resolve_interpreter().is_ok()
+    }
+
+    fn stops_at_deadline(&self) -> bool {
+        true
+    }
+
     fn execute(&self) -> Result<(), String> {
-        let status = Command::new(&self.interpreter)
-            .arg("-c")
-            .arg(&self.code)
-            .status()
-            .map_err(|e| format!("Command failed: {}", e))?;
-        if status.success() {
-            Ok(())
-        } else {
-            Err(format!("Command failed with status: {}", status))
-        }
+        self.run_code(&RunEnv::default()).result.map(drop)
+    }
+
+    fn run_in(&self, _node_id: &str, env: &RunEnv<'_>) -> Result<TaskOutcome, String> {
+        self.run_code(env).result
+    }
+
+    fn run_with_status(&self, _node_id: &str, env: &RunEnv<'_>) -> TaskRun {
+        self.run_code(env)
+    }
+}
+
+impl PythonTask {
+    fn run_code(&self, env: &RunEnv<'_>) -> TaskRun {
+        let interpreter = match self.resolve_interpreter() {
+            Ok(interpreter) => interpreter,
+            Err(err) => return Err(err).into(),
+        };
+        *self.interpreter_used.lock().unwrap() = Some(interpreter.to_string());
+        let spec = CommandSpec {
+            program: interpreter,
+            args: &["-c", &self.code],
+            env: &self.env,
+            secret_env: &self.secret_env,
+            cwd: None,
+            timeout: self.timeout,
+            capture: self.capture_output.then_some(self.max_output_bytes),
+            accepted_exit_codes: &[],
+            slots: self.slots.as_deref(),
+            log: env.log,
+            cancel: &[self.cancel.as_ref(), env.cancel],
+            deadline: env.deadline,
+            success_if: None,
+        };
+        run_command(&spec, &self.last_run)
     }
 }
 
@@ -107,6 +1458,177 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn fn_context_task_shares_values() {
+        use crate::dag::{Dag, TaskNode};
+
+        let mut dag = Dag::new("g".into());
+        let writer = FnContextTask::new(dag.context().clone(), |ctx| {
+            ctx.set("greeting", "hello");
+            Ok(())
+        });
+        let reader = FnContextTask::new(dag.context().clone(), |ctx| {
+            match ctx.get("greeting").as_deref() {
+                Some("hello") => Ok(()),
+                other => Err(format!("unexpected greeting: {:?}", other)),
+            }
+        });
+        dag.add_task_relation(
+            TaskNode::new("writer".to_string(), writer),
+            TaskNode::new("reader".to_string(), reader),
+        );
+
+        assert_eq!(dag.execute(), Ok(()));
+        assert_eq!(dag.context().get("greeting").as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn wrappers_pass_warnings_through() {
+        let task = RetryTask::new(
+            FnOutcomeTask::new(|| Ok(TaskOutcome::warning("disk almost full"))),
+            1,
+        );
+        assert_eq!(task.run(), Ok(TaskOutcome::warning("disk almost full")));
+        assert_eq!(task.execute(), Ok(()));
+    }
+
+    #[test]
+    fn retry_task_backs_off_on_the_clock() {
+        use crate::clock::MockClock;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let flaky = move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < 3 {
+                Err("not yet".to_string())
+            } else {
+                Ok(())
+            }
+        };
+        let clock = Arc::new(MockClock::new());
+        let t = RetryTask::new(flaky, 5)
+            .with_backoff(Duration::from_millis(100))
+            .with_clock(clock.clone());
+
+        assert!(t.execute().is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(
+            clock.sleeps(),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400)
+            ]
+        );
+        assert_eq!(clock.elapsed(), Duration::from_millis(700));
+
+        let t = RetryTask::new(|| Err("always".to_string()), 1).with_clock(clock);
+        assert_eq!(t.execute(), Err("always".to_string()));
+    }
+
+    #[test]
+    fn retry_task_stops_after_its_max_duration() {
+        use crate::clock::MockClock;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let clock = Arc::new(MockClock::new());
+        let calls = Arc::new(AtomicU32::new(0));
+        let (counter, now) = (calls.clone(), clock.clone());
+        // Each attempt takes a simulated 30s
+        let failing = move || {
+            now.advance(Duration::from_secs(30));
+            Err(format!(
+                "attempt {}",
+                counter.fetch_add(1, Ordering::SeqCst) + 1
+            ))
+        };
+        let t = RetryTask::for_duration(failing, Duration::from_secs(300))
+            .with_backoff(Duration::from_secs(10))
+            .with_clock(clock.clone());
+
+        // Attempts start at 0s, 40s, 90s, 160s and 270s; the next would start at 460s
+        assert_eq!(t.execute(), Err("attempt 5".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert_eq!(clock.elapsed(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn retry_tasks_share_a_rate_limit() {
+        use crate::clock::MockClock;
+        use crate::dag::{Dag, TaskNode};
+
+        let clock = Arc::new(MockClock::new());
+        let limiter =
+            Arc::new(RetryLimiter::new(2, Duration::from_millis(100)).with_clock(clock.clone()));
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+
+        let mut dag = Dag::new("g".into());
+        let mut previous: Option<TaskNode> = None;
+        for name in ["a", "b", "c"] {
+            let (now, attempts) = (clock.clone(), attempts.clone());
+            let calls = Mutex::new(0);
+            // Fails twice, then succeeds
+            let flaky = move || {
+                let mut calls = calls.lock().unwrap();
+                *calls += 1;
+                attempts.lock().unwrap().push((*calls, now.elapsed()));
+                if *calls < 3 {
+                    Err("busy".to_string())
+                } else {
+                    Ok(())
+                }
+            };
+            let task = RetryTask::new(flaky, 2)
+                .with_clock(clock.clone())
+                .with_limiter(limiter.clone());
+            let node = TaskNode::new(name.to_string(), task);
+            match previous.replace(node.clone()) {
+                Some(previous) => dag.add_task_relation(previous, node),
+                None => dag.add_task(node),
+            }
+        }
+        assert_eq!(dag.execute(), Ok(()));
+
+        let retries: Vec<Duration> = attempts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(call, _)| *call > 1)
+            .map(|&(_, at)| at)
+            .collect();
+        assert_eq!(
+            retries,
+            [0, 0, 50, 100, 150, 200]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+        // Beyond the initial burst of 2, retry n waits for n - 1 refills of 50ms
+        for (n, at) in retries.iter().enumerate().skip(2) {
+            assert!(*at >= Duration::from_millis(50) * (n as u32 - 1));
+        }
+    }
+
+    #[test]
+    fn timeout_task_uses_the_clock() {
+        use crate::clock::MockClock;
+
+        // The inner task never finishes on its own; only simulated time passes
+        let (_keep_open, rx) = mpsc::channel::<()>();
+        let rx = Mutex::new(rx);
+        let blocked = move || {
+            let _ = rx.lock().unwrap().recv();
+            Ok(())
+        };
+        let clock = Arc::new(MockClock::new());
+        let t = TimeoutTask::new(blocked, Duration::from_secs(30)).with_clock(clock.clone());
+        assert_eq!(t.execute(), Err("timed out after 30s".to_string()));
+        assert!(clock.elapsed() >= Duration::from_secs(30));
+
+        let t = TimeoutTask::new(|| Ok(()), Duration::from_secs(1));
+        assert!(t.execute().is_ok());
+    }
+
     #[test]
     fn shell_task_ok() {
         // 'true' is a standard POSIX command that exits 0
@@ -121,6 +1643,286 @@ mod tests {
         assert!(t.execute().is_err());
     }
 
+    #[test]
+    fn shell_task_accepts_listed_exit_codes() {
+        let t = ShellTask::new("exit 1").accept_exit_codes(vec![1]);
+        assert!(t.execute().is_ok());
+        let t = ShellTask::new("exit 2").accept_exit_codes(vec![1]);
+        assert!(t.execute().is_err());
+    }
+
+    #[test]
+    fn shell_task_success_predicate_overrides_exit_code() {
+        fn no_errors(stdout: &str, _stderr: &str, code: i32) -> bool {
+            code == 0 && !stdout.contains("ERROR")
+        }
+        let t = ShellTask::new("echo 'ERROR: disk full'").success_if(no_errors);
+        let err = t.execute().unwrap_err();
+        assert_eq!(
+            err,
+            "Command failed its success check with status: exit status: 0"
+        );
+        assert_eq!(t.last_output().unwrap().stdout, "ERROR: disk full\n");
+        assert!(
+            ShellTask::new("echo fine")
+                .success_if(no_errors)
+                .execute()
+                .is_ok()
+        );
+
+        // The predicate can also accept a non-zero exit
+        let t = ShellTask::new("echo warn >&2; exit 3")
+            .success_if(|_, stderr, code| code == 3 && stderr == "warn\n");
+        assert!(t.execute().is_ok());
+    }
+
+    #[test]
+    fn shell_task_captures_output() {
+        let t = ShellTask::new("echo out; echo err >&2").capture_output();
+        assert!(t.execute().is_ok());
+        let output = t.last_output().unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+    }
+
+    #[test]
+    fn shell_task_truncates_output_beyond_cap() {
+        let t = ShellTask::new("head -c 5000 /dev/zero | tr '\\0' a")
+            .capture_output()
+            .with_max_output_bytes(1000);
+        assert!(t.execute().is_ok());
+        let stdout = t.last_output().unwrap().stdout;
+        assert!(stdout.starts_with(&"a".repeat(1000)));
+        assert!(stdout.ends_with("[output truncated: 1000 of 5000 bytes kept]"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_task_timeout_kills_process() {
+        let t = ShellTask::new("sleep 10").with_timeout(Duration::from_secs(1));
+        let started = Instant::now();
+        assert_eq!(t.execute(), Err("timed out; process killed".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let t = ShellTask::new("true").with_timeout(Duration::from_secs(5));
+        assert!(t.execute().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_task_timeout_kills_grandchildren_holding_captured_output() {
+        // The shell forks both sleeps, which inherit its stdout and stderr pipes
+        let t = ShellTask::new("sleep 10 & sleep 10; true")
+            .capture_output()
+            .with_timeout(Duration::from_millis(200));
+        let started = Instant::now();
+        assert_eq!(t.execute(), Err("timed out; process killed".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_task_times_out_waiting_for_a_slot() {
+        let slots =
+            Arc::new(ProcessSlots::new(1).with_permit_acquire_timeout(Duration::from_millis(100)));
+        let busy = ShellTask::new("sleep 1").with_slots(slots.clone());
+        let blocked = ShellTask::new("true").with_slots(slots.clone());
+
+        thread::scope(|scope| {
+            let running = scope.spawn(|| busy.execute());
+            while slots.in_use() == 0 {
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert_eq!(
+                blocked.execute(),
+                Err("could not acquire execution slot".to_string())
+            );
+            assert!(running.join().unwrap().is_ok());
+        });
+        assert_eq!(slots.in_use(), 0);
+        assert!(blocked.execute().is_ok());
+    }
+
+    #[test]
+    fn shell_task_renders_cwd_and_command_from_context() {
+        let dir = std::env::temp_dir().join(format!("seadog-cwd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let context = Context::new();
+        context.set("input_dir", dir.to_str().unwrap());
+        context.set("file", "marker.txt");
+
+        let task = ShellTask::new("pwd > ${file}")
+            .in_dir("${input_dir}")
+            .with_context(context.clone());
+        assert!(task.execute().is_ok());
+        let written = std::fs::read_to_string(dir.join("marker.txt")).unwrap();
+        assert_eq!(
+            Path::new(written.trim()).canonicalize().unwrap(),
+            dir.canonicalize().unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let missing = ShellTask::new("true")
+            .in_dir("${nope}")
+            .with_context(context);
+        assert_eq!(
+            missing.execute(),
+            Err("context has no value for '${nope}'".to_string())
+        );
+    }
+
+    #[test]
+    fn shell_and_python_tasks_report_exits_alike() {
+        let shell = ShellTask::new("echo \"$GREETING\"; exit 3")
+            .with_env("GREETING", "hi")
+            .capture_output();
+        let shell_err = shell.execute().unwrap_err();
+        assert_eq!(shell.last_output().unwrap().stdout, "hi\n");
+
+        if !has_python3() {
+            eprintln!("python3 not available; skipping");
+            return;
+        }
+        let python = PythonTask::new("import os, sys; print(os.environ['GREETING']); sys.exit(3)")
+            .with_env("GREETING", "hi")
+            .capture_output();
+        assert_eq!(python.execute().unwrap_err(), shell_err);
+        assert_eq!(python.last_output().unwrap(), shell.last_output().unwrap());
+        assert_eq!(
+            PythonTask::new("pass").execute(),
+            ShellTask::new("true").execute()
+        );
+    }
+
+    #[test]
+    fn python_task_reports_missing_interpreter() {
+        let task = PythonTask::with_interpreter("pass", "python-does-not-exist-3.99");
+        assert_eq!(
+            task.execute(),
+            Err("interpreter 'python-does-not-exist-3.99' not found on PATH".to_string())
+        );
+        assert!(find_executable("sh", None).is_some());
+        assert!(find_executable("/bin/sh", None).is_some());
+        assert!(find_executable("/no/such/python", None).is_none());
+    }
+
+    #[test]
+    fn secret_env_reaches_the_child_but_is_redacted() {
+        let task = ShellTask::new("echo \"$API_TOKEN\"")
+            .with_secret_env("API_TOKEN", "hunter2")
+            .capture_output();
+        assert_eq!(task.execute(), Ok(()));
+        assert_eq!(task.last_output().unwrap().stdout, "hunter2\n");
+
+        let debug = format!("{:?}", task);
+        assert!(debug.contains("\"API_TOKEN\": \"***\""), "{}", debug);
+        assert!(!debug.contains("hunter2"));
+        assert!(!task.fingerprint().unwrap().contains("hunter2"));
+        assert!(
+            task.params()
+                .values()
+                .all(|value| !value.contains("hunter2"))
+        );
+        let other = ShellTask::new(task.command.clone()).with_secret_env("API_TOKEN", "other");
+        assert_ne!(other.fingerprint(), task.fingerprint());
+
+        let python = PythonTask::new("pass").with_secret_env("API_TOKEN", "hunter2");
+        assert!(!format!("{:?}", python).contains("hunter2"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_task_looks_for_relative_programs_in_its_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("seadog-tools-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        let tool = dir.join("bin").join("tool");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let root = dir.to_str().unwrap();
+        assert!(
+            ShellTask::new("bin/tool --fast")
+                .in_dir(root)
+                .executable_found()
+        );
+        assert!(!ShellTask::new("bin/tool --fast").executable_found());
+        assert!(!ShellTask::new("./tool").in_dir(root).executable_found());
+        // The directory is rendered from the task's context first
+        let context = Context::new();
+        context.set("root", root);
+        let task = ShellTask::new("./bin/tool")
+            .in_dir("${root}")
+            .with_context(context);
+        assert!(task.executable_found());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelling_kills_a_running_process() {
+        let cancel = CancelToken::new();
+        let task = ShellTask::new("sleep 10").with_cancel_token(cancel.clone());
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        });
+
+        let started = Instant::now();
+        assert_eq!(task.execute(), Err("cancelled; process killed".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        canceller.join().unwrap();
+        // A cancelled token stops the next run before it spawns anything
+        assert_eq!(task.execute(), Err("cancelled".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn concurrent_runs_each_return_their_own_exit_code() {
+        let dir = std::env::temp_dir().join(format!("seadog-status-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // The first run takes the lock and exits 1 late; the second finds it and exits 2
+        let task = Arc::new(ShellTask::new(format!(
+            "if mkdir '{}/lock' 2>/dev/null; then sleep 0.3; exit 1; else exit 2; fi",
+            dir.display()
+        )));
+        let first = {
+            let task = task.clone();
+            thread::spawn(move || task.run_with_status("first", &RunEnv::default()))
+        };
+        thread::sleep(Duration::from_millis(100));
+        let second = task.run_with_status("second", &RunEnv::default());
+        let first = first.join().unwrap();
+
+        assert_eq!(second.exit_code, Some(2));
+        assert_eq!(first.exit_code, Some(1));
+        assert!(first.result.is_err() && second.result.is_err());
+        // The shared state only knows about whichever run finished last
+        assert_eq!(task.exit_code(), Some(1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn python_task_falls_back_to_an_available_interpreter() {
+        let Some(expected) = PYTHON_FALLBACKS
+            .into_iter()
+            .find(|name| find_executable(name, None).is_some())
+        else {
+            eprintln!("no python interpreter available; skipping test");
+            return;
+        };
+
+        let task = PythonTask::with_interpreter("pass", "python-does-not-exist-3.99");
+        assert!(task.execute().is_err());
+        assert_eq!(task.interpreter_used(), None);
+
+        let task = task.with_interpreter_fallback();
+        assert_eq!(task.execute(), Ok(()));
+        assert_eq!(task.interpreter_used().as_deref(), Some(expected));
+    }
+
     #[test]
     fn python_task_ok_if_available() {
         if !has_python3() {
@@ -150,4 +1952,22 @@ mod tests {
         let t = PythonTask::with_interpreter("print('custom')", "python3");
         assert!(t.execute().is_ok());
     }
+
+    #[test]
+    fn built_in_type_names() {
+        struct Custom;
+        impl Task for Custom {
+            fn execute(&self) -> Result<(), String> {
+                Ok(())
+            }
+        }
+        fn ok() -> Result<(), String> {
+            Ok(())
+        }
+
+        assert_eq!(ShellTask::new("true").type_name(), "shell");
+        assert_eq!(PythonTask::new("pass").type_name(), "python");
+        assert_eq!(Task::type_name(&ok), "closure");
+        assert_eq!(Custom.type_name(), "task");
+    }
 }
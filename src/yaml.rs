@@ -0,0 +1,649 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dag::{Dag, TaskNode};
+use crate::task::{DEFAULT_MAX_OUTPUT_BYTES, PythonTask, ShellTask};
+
+/// Serialized form of a DAG, shared by `Dag::to_yaml` and `Dag::from_yaml`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DagSpec {
+    pub name: String,
+    #[serde(default)]
+    pub tasks: Vec<TaskSpec>,
+}
+
+/// Serialized form of a single node.
+///
+/// Task parameters (`command` and `cwd` for shell tasks, `code` and `interpreter` for python
+/// tasks) sit next to the other keys, along with the optional `accept_exit_codes` (shell
+/// only, comma-separated), `capture_output`, `max_output_bytes`, `timeout_ms` and one
+/// `env.NAME` key per environment variable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default = "default_cost", skip_serializing_if = "is_default_cost")]
+    pub cost: usize,
+    #[serde(default, skip_serializing_if = "is_zero_u32")]
+    pub retries: u32,
+    #[serde(default, skip_serializing_if = "is_zero_i32")]
+    pub priority: i32,
+    #[serde(flatten)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// A task definition fed to `Dag::from_records`; the same shape as a YAML task entry.
+pub type TaskRecord = TaskSpec;
+
+fn default_cost() -> usize {
+    1
+}
+
+fn is_default_cost(cost: &usize) -> bool {
+    *cost == 1
+}
+
+fn is_zero_u32(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_zero_i32(value: &i32) -> bool {
+    *value == 0
+}
+
+impl TaskSpec {
+    fn from_node(dag: &Dag, node: &TaskNode) -> Self {
+        TaskSpec {
+            name: node.name.clone(),
+            kind: node.task_type().to_string(),
+            depends_on: dag
+                .predecessors(node)
+                .iter()
+                .map(|upstream| upstream.name.clone())
+                .collect(),
+            cost: node.cost(),
+            retries: node.retries(),
+            priority: node.priority(),
+            params: node.task_params(),
+        }
+    }
+
+    pub(crate) fn to_node(&self) -> Result<TaskNode, String> {
+        let param = |key: &str| {
+            self.params.get(key).cloned().ok_or_else(|| {
+                format!(
+                    "Task '{}' of type '{}' is missing '{}'",
+                    self.name, self.kind, key
+                )
+            })
+        };
+
+        let node = match self.kind.as_str() {
+            "shell" => {
+                let mut task = ShellTask::new(param("command")?);
+                task.cwd = self.params.get("cwd").cloned();
+                if let Some(codes) = self.params.get("accept_exit_codes") {
+                    task.accepted_exit_codes = codes
+                        .split(',')
+                        .map(|code| self.parse("accept_exit_codes", code.trim()))
+                        .collect::<Result<_, _>>()?;
+                }
+                task.capture_output = self.flag("capture_output")?;
+                task.max_output_bytes = self.max_output_bytes()?;
+                task.timeout = self.timeout()?;
+                task.env = self.env();
+                TaskNode::new(self.name.clone(), task)
+            }
+            "python" => {
+                let code = param("code")?;
+                let mut task = match self.params.get("interpreter") {
+                    Some(interpreter) => PythonTask::with_interpreter(code, interpreter.clone()),
+                    None => PythonTask::new(code),
+                };
+                task.capture_output = self.flag("capture_output")?;
+                task.max_output_bytes = self.max_output_bytes()?;
+                task.timeout = self.timeout()?;
+                task.env = self.env();
+                TaskNode::new(self.name.clone(), task)
+            }
+            other => {
+                return Err(format!(
+                    "Task '{}' has type '{}', which cannot be loaded",
+                    self.name, other
+                ));
+            }
+        };
+
+        Ok(node
+            .with_cost(self.cost)
+            .with_retries(self.retries)
+            .with_priority(self.priority))
+    }
+
+    fn parse<T: FromStr>(&self, key: &str, value: &str) -> Result<T, String> {
+        value
+            .parse()
+            .map_err(|_| format!("Task '{}' has an invalid '{}': '{}'", self.name, key, value))
+    }
+
+    fn flag(&self, key: &str) -> Result<bool, String> {
+        self.params
+            .get(key)
+            .map_or(Ok(false), |value| self.parse(key, value))
+    }
+
+    fn max_output_bytes(&self) -> Result<usize, String> {
+        self.params
+            .get("max_output_bytes")
+            .map_or(Ok(DEFAULT_MAX_OUTPUT_BYTES), |value| {
+                self.parse("max_output_bytes", value)
+            })
+    }
+
+    fn timeout(&self) -> Result<Option<Duration>, String> {
+        self.params
+            .get("timeout_ms")
+            .map(|value| self.parse("timeout_ms", value).map(Duration::from_millis))
+            .transpose()
+    }
+
+    fn env(&self) -> Vec<(String, String)> {
+        self.params
+            .iter()
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix("env.")?;
+                Some((name.to_string(), value.clone()))
+            })
+            .collect()
+    }
+}
+
+impl DagSpec {
+    /// Captures the structure of `dag`, listing tasks in execution order when possible.
+    pub fn from_dag(dag: &Dag) -> Self {
+        let nodes = dag.resolve_execution_order().unwrap_or_else(|_| {
+            let mut nodes = dag.get_all_tasks();
+            nodes.sort_by(|a, b| a.name.cmp(&b.name));
+            nodes
+        });
+
+        DagSpec {
+            name: dag.name().to_string(),
+            tasks: nodes
+                .iter()
+                .map(|node| TaskSpec::from_node(dag, node))
+                .collect(),
+        }
+    }
+
+    /// Builds a DAG; dependencies may refer to tasks listed later.
+    pub fn into_dag(self) -> Result<Dag, String> {
+        let mut dag = Dag::from_records(self.tasks)?;
+        dag.set_name(self.name);
+        Ok(dag)
+    }
+}
+
+impl Dag {
+    /// Builds a DAG named "records" one record at a time
+    ///
+    /// Each record becomes a node as soon as it is read. A dependency on a task that has
+    /// not appeared yet is remembered and resolved once the iterator is exhausted, so
+    /// only those pending edges are held in memory besides the DAG itself.
+    pub fn from_records(records: impl IntoIterator<Item = TaskRecord>) -> Result<Dag, String> {
+        let mut builder = RecordBuilder::new();
+        for record in records {
+            builder.add(record)?;
+        }
+        builder.finish("records".to_string())
+    }
+
+    /// Like `from_yaml`, reading one task entry at a time so memory stays bounded
+    ///
+    /// The block list under the top-level `tasks:` key is split into entries by
+    /// indentation, and each entry is parsed and added to the DAG before the next one is
+    /// read. Besides the DAG, only the current entry, the other top-level keys and edges
+    /// to tasks not seen yet are held in memory. A `tasks` list in flow style
+    /// (`tasks: [...]`) is read whole, and anchors cannot be shared between entries,
+    /// since each one is parsed on its own.
+    pub fn from_yaml_reader<R: Read>(reader: R) -> Result<Dag, String> {
+        let mut reader = BufReader::new(reader);
+        let mut builder = RecordBuilder::new();
+        let mut header = String::new();
+        let mut entry = TaskEntry::default();
+        // Indentation of the `tasks` entries, once inside the list and past its first `-`
+        let mut tasks: Option<Option<usize>> = None;
+        let mut line = String::new();
+        let mut number = 0;
+        loop {
+            line.clear();
+            let read = reader
+                .read_line(&mut line)
+                .map_err(|e| format!("Invalid YAML: {}", e))?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+            let content = line.trim_start_matches(' ');
+            let indent = line.len() - content.len();
+            if let Some(entries) = tasks {
+                let blank = content.trim().is_empty() || content.starts_with('#');
+                let item = content.starts_with('-')
+                    && content[1..].starts_with(|c: char| c.is_ascii_whitespace());
+                match entries {
+                    _ if blank => {
+                        if !entry.text.is_empty() {
+                            entry.text.push_str(&line);
+                        }
+                        continue;
+                    }
+                    None if item => tasks = Some(Some(indent)),
+                    Some(entries) if indent > entries => {
+                        entry.text.push_str(&line);
+                        continue;
+                    }
+                    Some(entries) if indent == entries && item => {}
+                    None => {
+                        // Not a block list; leave it to the whole-document parser
+                        header.push_str("tasks:\n");
+                        tasks = None;
+                    }
+                    Some(_) => tasks = None,
+                }
+                entry.add_to(&mut builder)?;
+                if tasks.is_some() {
+                    entry.start = number;
+                    entry.text.push_str(&line);
+                    continue;
+                }
+            }
+            let rest = content.strip_prefix("tasks:").map(str::trim_start);
+            if indent == 0 && rest.is_some_and(|rest| rest.is_empty() || rest.starts_with('#')) {
+                tasks = Some(None);
+            } else {
+                header.push_str(&line);
+            }
+        }
+        entry.add_to(&mut builder)?;
+
+        let spec: DagSpec =
+            serde_yaml::from_str(&header).map_err(|e| format!("Invalid YAML: {}", e))?;
+        for record in spec.tasks {
+            builder.add(record)?;
+        }
+        builder.finish(spec.name)
+    }
+
+    /// Loads a DAG from YAML
+    ///
+    /// ```yaml
+    /// name: pipeline
+    /// tasks:
+    ///   - name: fetch
+    ///     type: shell
+    ///     command: curl -O https://example.com/data.csv
+    ///   - name: clean
+    ///     type: python
+    ///     code: import csv
+    ///     depends_on: [fetch]
+    /// ```
+    ///
+    /// Only `shell` and `python` tasks can be loaded. Task names must be unique.
+    pub fn from_yaml(src: &str) -> Result<Dag, String> {
+        let spec: DagSpec =
+            serde_yaml::from_str(src).map_err(|e| format!("Invalid YAML: {}", e))?;
+        spec.into_dag()
+    }
+
+    /// Exports the DAG as YAML in the format read by `from_yaml`
+    ///
+    /// Tasks without serializable parameters, such as closures, are written with their
+    /// type name only and cannot be loaded back. Dependencies are written by name, so
+    /// names should be unique for the output to round-trip.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&DagSpec::from_dag(self)).expect("DAG specs always serialize")
+    }
+}
+
+/// Turns task records into a DAG one at a time, holding back edges to tasks that
+/// have not been seen yet.
+struct RecordBuilder {
+    dag: Dag,
+    nodes: HashMap<String, TaskNode>,
+    pending: Vec<(String, String)>,
+}
+
+impl RecordBuilder {
+    fn new() -> Self {
+        RecordBuilder {
+            dag: Dag::new("records".to_string()),
+            nodes: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, record: TaskRecord) -> Result<(), String> {
+        let dag = &mut self.dag;
+        let node = record.to_node()?;
+        if self.nodes.contains_key(&record.name) {
+            return Err(format!("Task '{}' is defined more than once", record.name));
+        }
+        dag.add_task(node.clone());
+        for dependency in record.depends_on {
+            match self.nodes.get(&dependency) {
+                Some(upstream) => dag.add_task_relation(upstream.clone(), node.clone()),
+                None => self.pending.push((record.name.clone(), dependency)),
+            }
+        }
+        self.nodes.insert(record.name, node);
+        Ok(())
+    }
+
+    /// Resolves the held-back edges and names the finished DAG.
+    fn finish(self, name: String) -> Result<Dag, String> {
+        let mut dag = self.dag;
+        dag.set_name(name);
+        for (task, dependency) in self.pending {
+            let upstream = self.nodes.get(&dependency).ok_or_else(|| {
+                format!("Task '{}' depends on unknown task '{}'", task, dependency)
+            })?;
+            dag.add_task_relation(upstream.clone(), self.nodes[&task].clone());
+        }
+        Ok(dag)
+    }
+}
+
+/// Text of one `tasks` entry for `Dag::from_yaml_reader`, starting with its `-`.
+#[derive(Default)]
+struct TaskEntry {
+    text: String,
+    /// Line the entry starts on, for errors.
+    start: usize,
+}
+
+impl TaskEntry {
+    /// Parses the entry, if any, into `builder` and clears it for the next one.
+    fn add_to(&mut self, builder: &mut RecordBuilder) -> Result<(), String> {
+        if self.text.is_empty() {
+            return Ok(());
+        }
+        let records: Vec<TaskRecord> = serde_yaml::from_str(&self.text).map_err(|e| {
+            format!(
+                "Invalid YAML in the task entry at line {}: {}",
+                self.start, e
+            )
+        })?;
+        self.text.clear();
+        records
+            .into_iter()
+            .try_for_each(|record| builder.add(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    type Pairs = BTreeSet<(String, String)>;
+
+    /// Node names with task types, and edges by name.
+    fn structure(dag: &Dag) -> (Pairs, Pairs) {
+        let nodes = dag
+            .get_all_tasks()
+            .iter()
+            .map(|node| (node.name.clone(), node.task_type().to_string()))
+            .collect();
+        let edges = dag
+            .edges()
+            .map(|(from, to)| (from.name.clone(), to.name.clone()))
+            .collect();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let mut dag = Dag::new("pipeline".into());
+        let fetch = TaskNode::new(
+            "fetch".to_string(),
+            ShellTask::new("echo fetch").in_dir("/tmp"),
+        );
+        let clean = TaskNode::new(
+            "clean".to_string(),
+            PythonTask::with_interpreter("print('clean')", "python3.12"),
+        )
+        .with_retries(2);
+        let report =
+            TaskNode::new("report".to_string(), ShellTask::new("echo \"done\"")).with_priority(3);
+        dag.add_task_relation(fetch.clone(), clean.clone());
+        dag.add_task_relation(fetch.clone(), report.clone());
+        dag.add_task_relation(clean.clone(), report.clone());
+
+        let yaml = dag.to_yaml();
+        let loaded = Dag::from_yaml(&yaml).unwrap();
+        assert_eq!(structure(&loaded), structure(&dag));
+        assert_eq!(loaded.to_yaml(), yaml);
+
+        let clean = loaded
+            .get_all_tasks()
+            .into_iter()
+            .find(|node| node.name == "clean")
+            .unwrap();
+        assert_eq!(clean.retries(), 2);
+        assert_eq!(clean.task_params()["interpreter"], "python3.12");
+        assert!(loaded.to_yaml().contains("cwd: /tmp"));
+    }
+
+    #[test]
+    fn yaml_round_trip_keeps_shell_settings() {
+        let mut dag = Dag::new("settings".into());
+        let lenient = TaskNode::new(
+            "lenient".to_string(),
+            ShellTask::new("exit 1")
+                .accept_exit_codes(vec![1, 2])
+                .with_env("FOO", "bar")
+                .capture_output()
+                .with_timeout(Duration::from_millis(1500)),
+        );
+        let script = TaskNode::new(
+            "script".to_string(),
+            PythonTask::new("print(1)").with_env("MODE", "fast"),
+        );
+        dag.add_task_relation(lenient, script);
+
+        let yaml = dag.to_yaml();
+        let loaded = Dag::from_yaml(&yaml).unwrap();
+        assert_eq!(loaded.to_yaml(), yaml);
+        let params = |name: &str| {
+            loaded
+                .get_all_tasks()
+                .into_iter()
+                .find(|node| node.name == name)
+                .unwrap()
+                .task_params()
+        };
+        let lenient = params("lenient");
+        assert_eq!(lenient["accept_exit_codes"], "1,2");
+        assert_eq!(lenient["env.FOO"], "bar");
+        assert_eq!(lenient["capture_output"], "true");
+        assert_eq!(lenient["timeout_ms"], "1500");
+        assert_eq!(params("script")["env.MODE"], "fast");
+
+        // A loaded task still accepts the exit code and sees the variable
+        let src = "
+name: g
+tasks:
+  - name: check
+    type: shell
+    command: 'test \"$FOO\" = bar && exit 1'
+    accept_exit_codes: '1'
+    env.FOO: bar
+";
+        assert_eq!(Dag::from_yaml(src).unwrap().try_execute().1, Ok(()));
+
+        let bad = yaml.replace("timeout_ms: '1500'", "timeout_ms: soon");
+        assert!(
+            Dag::from_yaml(&bad)
+                .unwrap_err()
+                .contains("invalid 'timeout_ms'")
+        );
+    }
+
+    #[test]
+    fn yaml_resolves_forward_references() {
+        let src = "
+name: g
+tasks:
+  - name: b
+    type: shell
+    command: 'true'
+    depends_on: [a]
+  - name: a
+    type: shell
+    command: 'true'
+";
+        let dag = Dag::from_yaml(src).unwrap();
+        let order = dag.execution_order_names().unwrap();
+        assert_eq!(order, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn yaml_rejects_unloadable_tasks() {
+        let mut dag = Dag::new("g".into());
+        dag.add_task(TaskNode::new("native".to_string(), || Ok(())));
+        let err = Dag::from_yaml(&dag.to_yaml()).unwrap_err();
+        assert!(err.contains("'closure'"));
+
+        let err = Dag::from_yaml("name: g\ntasks:\n  - name: a\n    type: shell\n").unwrap_err();
+        assert!(err.contains("missing 'command'"));
+    }
+
+    #[test]
+    fn records_resolve_dependencies_declared_early() {
+        let record = |name: &str, depends_on: &[&str]| TaskRecord {
+            name: name.to_string(),
+            kind: "shell".to_string(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            cost: 1,
+            retries: 0,
+            priority: 0,
+            params: BTreeMap::from([("command".to_string(), "true".to_string())]),
+        };
+        let records = vec![
+            record("load", &["clean"]),
+            record("clean", &["fetch"]),
+            record("fetch", &[]),
+        ];
+
+        let dag = Dag::from_records(records.clone()).unwrap();
+        let order = dag.execution_order_names().unwrap();
+        assert_eq!(order, vec!["fetch", "clean", "load"]);
+
+        let err = Dag::from_records(records.into_iter().take(2)).unwrap_err();
+        assert_eq!(err, "Task 'clean' depends on unknown task 'fetch'");
+    }
+
+    #[test]
+    fn yaml_reader_builds_large_dags_with_forward_references() {
+        use std::fmt::Write as _;
+
+        // Every task depends on the one listed after it
+        const TASKS: usize = 10_000;
+        let mut src = String::from("name: huge\ntasks:\n");
+        for i in 0..TASKS {
+            let _ = writeln!(
+                src,
+                "  - name: t{}\n    type: shell\n    command: echo {}",
+                i, i
+            );
+            if i + 1 < TASKS {
+                let _ = writeln!(src, "    depends_on: [t{}]", i + 1);
+            }
+        }
+        let path = std::env::temp_dir().join(format!("seadog-huge-{}.yaml", std::process::id()));
+        std::fs::write(&path, &src).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let dag = Dag::from_yaml_reader(std::io::BufReader::new(file)).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(dag.name(), "huge");
+        assert_eq!(dag.get_all_tasks().len(), TASKS);
+        assert_eq!(dag.edges().count(), TASKS - 1);
+        let order = dag.execution_order_names().unwrap();
+        assert_eq!(order.first().map(String::as_str), Some("t9999"));
+        assert_eq!(order.last().map(String::as_str), Some("t0"));
+    }
+
+    #[test]
+    fn yaml_reader_splits_entries_in_any_layout() {
+        let src = "
+# pipeline
+name: g
+tasks:
+- name: a
+  type: shell
+  command: |
+    echo one
+    - not an entry
+  depends_on:
+  - b
+
+  # the other one
+-   name: b
+    type: python
+    code: print(1)
+retries_note: top-level keys after the list are kept out of the entries
+";
+        let dag = Dag::from_yaml_reader(src.as_bytes()).unwrap();
+        assert_eq!(dag.name(), "g");
+        assert_eq!(dag.execution_order_names().unwrap(), vec!["b", "a"]);
+        let a = dag
+            .get_all_tasks()
+            .into_iter()
+            .find(|node| node.name == "a")
+            .unwrap();
+        assert_eq!(a.task_params()["command"], "echo one\n- not an entry\n");
+
+        // Flow lists are read whole, and `to_yaml` output reads back the same
+        let flow = "tasks: [{name: a, type: shell, command: 'true'}]\nname: f\n";
+        let dag = Dag::from_yaml_reader(flow.as_bytes()).unwrap();
+        assert_eq!(dag.name(), "f");
+        assert_eq!(dag.get_all_tasks().len(), 1);
+        let yaml = Dag::from_yaml(src).unwrap().to_yaml();
+        assert_eq!(
+            Dag::from_yaml_reader(yaml.as_bytes()).unwrap().to_yaml(),
+            yaml
+        );
+    }
+
+    #[test]
+    fn yaml_reader_reports_task_errors_unchanged() {
+        let read = |src: &str| Dag::from_yaml_reader(src.as_bytes());
+        let duplicate = "name: g\ntasks:\n  - {name: a, type: shell, command: 'true'}\n  - {name: a, type: shell, command: 'true'}\n";
+        assert_eq!(
+            read(duplicate).unwrap_err(),
+            "Task 'a' is defined more than once"
+        );
+        let unknown =
+            "name: g\ntasks:\n  - {name: a, type: shell, command: 'true', depends_on: [b]}\n";
+        assert_eq!(
+            read(unknown).unwrap_err(),
+            "Task 'a' depends on unknown task 'b'"
+        );
+        assert!(read("tasks: []\n").unwrap_err().starts_with("Invalid YAML"));
+        assert!(read("name: [\n").unwrap_err().starts_with("Invalid YAML"));
+        let broken = "name: g\ntasks:\n  - {name: a, type: shell, command: 'true'}\n  - name: [b\n";
+        assert!(
+            read(broken)
+                .unwrap_err()
+                .starts_with("Invalid YAML in the task entry at line 4:")
+        );
+    }
+}
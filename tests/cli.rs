@@ -0,0 +1,71 @@
+use std::process::{Command, Output};
+
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_seadog-scheduler"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run the CLI")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn runs_a_yaml_dag() {
+    let output = cli(&["run", "tests/fixtures/pipeline.yaml"]);
+    assert!(output.status.success());
+
+    let stdout = stdout(&output);
+    for name in ["fetch", "clean", "publish"] {
+        assert!(stdout.contains(&format!("{} [", name)), "{}", stdout);
+    }
+    assert_eq!(stdout.matches("success").count(), 3, "{}", stdout);
+}
+
+#[test]
+fn runs_in_parallel() {
+    let output = cli(&["run", "tests/fixtures/pipeline.yaml", "--parallel", "2"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).matches("success").count(), 3);
+}
+
+#[test]
+fn dry_run_prints_the_plan() {
+    let output = cli(&["run", "tests/fixtures/pipeline.yaml", "--dry-run"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "fetch (shell)\nclean (shell) after fetch\npublish (shell) after clean\n"
+    );
+}
+
+#[test]
+fn dry_run_flags_missing_executables() {
+    let output = cli(&["run", "tests/fixtures/missing_tool.yaml", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "fetch (shell)\nconvert (shell) after fetch [executable not found]\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("executable not found for: convert"));
+}
+
+#[test]
+fn failing_run_exits_nonzero() {
+    let output = cli(&["run", "tests/fixtures/failing.yaml"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("build ["), "{}", stdout);
+    assert!(stdout.contains("skipped: upstream task 'build' failed"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Task 'build' failed"));
+}
+
+#[test]
+fn bad_arguments_print_usage() {
+    let output = cli(&["run"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage:"));
+}